@@ -1,47 +1,128 @@
+use probability::DamageDistribution;
 use seed::{prelude::*, *};
 use shared::*;
 use std::collections::HashSet;
+use std::mem::discriminant;
 use std::rc::Rc;
 
+mod probability;
 mod shared;
 
+// server to connect to, unless the page says otherwise (see `server_url`)
 #[cfg(debug_assertions)]
 const WS_URL: &str = "ws://127.0.0.1:9000/ws";
 
 #[cfg(not(debug_assertions))]
 const WS_URL: &str = "wss://initial---dice-combat-sxrrowqjgq-uk.a.run.app/ws";
 
+// local storage key of the guest player token the server gave us
+const TOKEN_KEY: &str = "dice-combat-token";
+
 // ------ ------
 //     Model
 // ------ ------
 
 pub struct Model {
     // WebSocket handling
+    server_url: String, // e.g. `ws://127.0.0.1:9000/ws`, which rooms are joined under
+    ws_url: String,     // without the token, which gets added when connecting
     web_socket: WebSocket,
     web_socket_reconnector: Option<StreamHandle>,
+    error: Option<String>, // last message the server rejected
+    matchmaking: Option<MatchmakingStatus>,
+    token: Option<String>,
+    profile: Option<PlayerProfile>,
+    name_input: String,
     // UI
     game_state: ClientGameState,
     drafted_dice: HashSet<usize>,
+    reward_die: Option<usize>,
+    selected_action: Option<usize>,
+    selected_target: Option<usize>,
+    log_filter: Option<String>, // only show log entries involving this combatant
+    log_panel: ElRef<web_sys::Element>,
 }
 
 // ------ ------
 //     Init
 // ------ ------
 
-fn init(_: Url, orders: &mut impl Orders<Msg>) -> Model {
+fn init(url: Url, orders: &mut impl Orders<Msg>) -> Model {
+    // count down the turn timer between updates from the server
+    orders.stream(streams::interval(1000, || Msg::Tick));
+    // open the page with `?room=<code>` to join a room other than the default one, `?pvp` to create
+    // it as a PvP match, and `?spectate` to watch without taking part
+    let search = url.search();
+    let server_url = server_url(&url);
+    let mut ws_url = match search.get("room").and_then(|values| values.first()) {
+        Some(room) => format!("{}/{}", server_url, room),
+        None => server_url.clone(),
+    };
+    let params: Vec<&str> = ["spectate", "pvp"]
+        .iter()
+        .copied()
+        .filter(|param| search.contains_key(param))
+        .collect();
+    if !params.is_empty() {
+        ws_url = format!("{}?{}", ws_url, params.join("&"));
+    }
+    let token = LocalStorage::get(TOKEN_KEY).ok();
     Model {
         game_state: ClientGameState {
             // Will be replaced once connected to server for first time
             client_phase: ClientPhase::Waiting,
             combatants: vec![],
+            current_combatant: None,
+            turn_time_left: None,
+            spectating: false,
+            seat: None,
             combat_log: vec![],
         },
         drafted_dice: HashSet::new(),
-        web_socket: create_websocket(orders),
+        reward_die: None,
+        selected_action: None,
+        selected_target: None,
+        log_filter: None,
+        log_panel: ElRef::new(),
+        web_socket: create_websocket(&with_token(&ws_url, &token), orders),
         web_socket_reconnector: None,
+        error: None,
+        matchmaking: None,
+        server_url,
+        ws_url,
+        token,
+        profile: None,
+        name_input: String::new(),
     }
 }
 
+/// The server to connect to: the page's `<meta name="dice-combat-server" content="<websocket url>">`, so a
+/// deployment can point the client at its server without a rebuild. Debug builds can also be opened with
+/// `?server=<websocket url>`, which release builds ignore, since the player's token is sent to whichever
+/// server the client connects to, and anyone could link to the page with their own server.
+fn server_url(url: &Url) -> String {
+    let from_query = url
+        .search()
+        .get("server")
+        .and_then(|values| values.first())
+        .filter(|_| cfg!(debug_assertions))
+        .cloned();
+    let from_page = || {
+        document()
+            .query_selector("meta[name=dice-combat-server]")
+            .ok()
+            .flatten()
+            .and_then(|meta| meta.get_attribute("content"))
+    };
+    from_query
+        .or_else(from_page)
+        .filter(|server_url| !server_url.is_empty())
+        .map_or_else(
+            || WS_URL.to_owned(),
+            |server_url| server_url.trim_end_matches('/').to_owned(),
+        )
+}
+
 // ------ ------
 //    Update
 // ------ ------
@@ -59,9 +140,25 @@ pub enum Msg {
     // UI handling
     DraftDie(usize),
     FinishDrafting,
+    SelectRewardDie(usize),
+    ChooseReward(usize),
+    SelectAction(usize),
+    ConfirmAction,
+    SelectTarget(usize),
+    ConfirmTarget,
+    CancelAction,
+    ChooseNode(usize),
+    StartRun,
+    FindMatch,
+    CancelFindMatch,
+    EditName(String),
+    SaveName,
+    FilterLog(String),
+    ScrollLog,
+    Tick,
 }
 
-fn update(msg: Msg, mut model: &mut Model, orders: &mut impl Orders<Msg>) {
+fn update(msg: Msg, model: &mut Model, orders: &mut impl Orders<Msg>) {
     match msg {
         // WebSocket handling
         Msg::WebSocketOpened => {
@@ -100,7 +197,7 @@ fn update(msg: Msg, mut model: &mut Model, orders: &mut impl Orders<Msg>) {
         }
         Msg::ReconnectWebSocket(retries) => {
             log!("Reconnect attempt:", retries);
-            model.web_socket = create_websocket(orders);
+            model.web_socket = create_websocket(&with_token(&model.ws_url, &model.token), orders);
         }
         Msg::SendMessage(msg) => {
             model.web_socket.send_json(&msg).unwrap();
@@ -109,8 +206,47 @@ fn update(msg: Msg, mut model: &mut Model, orders: &mut impl Orders<Msg>) {
             log!("got message from server and decoded it successfully");
             match message {
                 ServerMessage::NewState(client_state) => {
+                    // drafted dice are positions in the pool of the drafting phase they were picked in
+                    let same_phase = discriminant(&client_state.client_phase)
+                        == discriminant(&model.game_state.client_phase)
+                        && client_state.current_combatant == model.game_state.current_combatant;
+                    if !same_phase {
+                        model.drafted_dice.clear();
+                    }
                     model.game_state = client_state;
+                    model.selected_action = None;
+                    model.selected_target = None;
+                    model.error = None;
                     log!("new game state set");
+                    // keep the newest log entries in view
+                    orders.after_next_render(|_| Msg::ScrollLog);
+                }
+                ServerMessage::Error(error) => {
+                    log!("server rejected message:", error);
+                    model.error = Some(error);
+                }
+                ServerMessage::Matchmaking(status) => {
+                    // the match is played in its own room, so move over to it
+                    if let MatchmakingStatus::Found { room, seat } = &status {
+                        model.ws_url = format!("{}/{}?seat={}", model.server_url, room, seat);
+                        model.web_socket_reconnector = None;
+                        model
+                            .web_socket
+                            .close(None, Some("joining matched room"))
+                            .unwrap();
+                        model.web_socket =
+                            create_websocket(&with_token(&model.ws_url, &model.token), orders);
+                    }
+                    model.matchmaking = Some(status);
+                }
+                ServerMessage::Profile { token, profile } => {
+                    // keep the token, so we come back as the same player next time
+                    if let Err(err) = LocalStorage::insert(TOKEN_KEY, &token) {
+                        log!("could not store player token:", err);
+                    }
+                    model.token = Some(token);
+                    model.name_input = profile.name.clone();
+                    model.profile = Some(profile);
                 }
             }
         }
@@ -128,13 +264,102 @@ fn update(msg: Msg, mut model: &mut Model, orders: &mut impl Orders<Msg>) {
                 model.drafted_dice.iter().copied().collect(),
             ))
             .unwrap(),
+        Msg::SelectRewardDie(selected) => {
+            if model.reward_die == Some(selected) {
+                model.reward_die = None;
+            } else {
+                model.reward_die = Some(selected);
+            }
+        }
+        Msg::ChooseReward(reward) => {
+            model
+                .web_socket
+                .send_json(&ClientMessage::ChooseReward(reward, model.reward_die))
+                .unwrap();
+            model.reward_die = None;
+        }
+        Msg::SelectAction(selected) => model.selected_action = Some(selected),
+        Msg::ConfirmAction => {
+            if let Some(action) = model.selected_action {
+                model
+                    .web_socket
+                    .send_json(&ClientMessage::ChooseAction(action))
+                    .unwrap();
+            }
+        }
+        Msg::SelectTarget(selected) => model.selected_target = Some(selected),
+        Msg::ConfirmTarget => {
+            if let Some(target) = model.selected_target {
+                model
+                    .web_socket
+                    .send_json(&ClientMessage::ChooseTarget(target))
+                    .unwrap();
+            }
+        }
+        Msg::CancelAction => {
+            // once an action is confirmed, the server has to take us back to choosing an action
+            if let ClientPhase::SelectTarget(..) = model.game_state.client_phase {
+                model
+                    .web_socket
+                    .send_json(&ClientMessage::CancelAction)
+                    .unwrap();
+            }
+            model.selected_action = None;
+            model.selected_target = None;
+        }
+        Msg::ChooseNode(node) => model
+            .web_socket
+            .send_json(&ClientMessage::ChooseNode(node))
+            .unwrap(),
+        Msg::StartRun => model
+            .web_socket
+            .send_json(&ClientMessage::StartRun)
+            .unwrap(),
+        Msg::FindMatch => model
+            .web_socket
+            .send_json(&ClientMessage::FindMatch)
+            .unwrap(),
+        Msg::CancelFindMatch => model
+            .web_socket
+            .send_json(&ClientMessage::CancelFindMatch)
+            .unwrap(),
+        Msg::EditName(name) => model.name_input = name,
+        Msg::SaveName => model
+            .web_socket
+            .send_json(&ClientMessage::SetName(model.name_input.clone()))
+            .unwrap(),
+        Msg::FilterLog(combatant) => {
+            model.log_filter = if combatant.is_empty() {
+                None
+            } else {
+                Some(combatant)
+            };
+        }
+        Msg::Tick => {
+            if let Some(seconds) = model.game_state.turn_time_left.as_mut() {
+                *seconds = seconds.saturating_sub(1);
+            }
+        }
+        Msg::ScrollLog => {
+            if let Some(log_panel) = model.log_panel.get() {
+                log_panel.set_scroll_top(log_panel.scroll_height());
+            }
+        }
     }
 }
 
-fn create_websocket(orders: &impl Orders<Msg>) -> WebSocket {
+fn with_token(url: &str, token: &Option<String>) -> String {
+    match token {
+        Some(token) if url.contains('?') => format!("{}&token={}", url, token),
+        Some(token) => format!("{}?token={}", url, token),
+        None => url.to_owned(),
+    }
+}
+
+fn create_websocket(url: &str, orders: &impl Orders<Msg>) -> WebSocket {
     let msg_sender = orders.msg_sender();
 
-    WebSocket::builder(WS_URL, orders)
+    WebSocket::builder(url, orders)
         .on_open(|| Msg::WebSocketOpened)
         .on_message(move |msg| decode_message(msg, msg_sender))
         .on_close(Msg::WebSocketClosed)
@@ -179,7 +404,24 @@ fn view(model: &Model) -> Vec<Node<Msg>> {
                         div![
                             C!["card-body"],
                             h5![C!["card-title"], "Status"],
-                            p![C!["card-text"], format!("Connected to server: {}", WS_URL)],
+                            p![
+                                C!["card-text"],
+                                format!("Connected to server: {}", model.ws_url)
+                            ],
+                            IF!(model.game_state.spectating => p![
+                                C!["card-text"],
+                                span![C!["badge bg-secondary"], "Spectating"]
+                            ]),
+                            model.game_state.seat.map(|seat| p![
+                                C!["card-text"],
+                                span![C!["badge bg-primary"], format!("Player {}", seat + 1)]
+                            ]),
+                            model.error.as_ref().map(|error| div![
+                                C!["alert alert-danger"],
+                                error
+                            ]),
+                            model.profile.as_ref().map(|profile| view_profile(model, profile)),
+                            IF!(model.game_state.seat.is_none() && !model.game_state.spectating => view_matchmaking(model)),
                         ]
                     ]
                 ],
@@ -192,7 +434,16 @@ fn view(model: &Model) -> Vec<Node<Msg>> {
                             C!["card text-white bg-dark"],
                             div![
                                 C!["card-body"],
+                                model.game_state.turn_time_left.map(|seconds| {
+                                    p![
+                                        C!["text-warning float-end"],
+                                        format!("Time left: {}s", seconds)
+                                    ]
+                                }),
                                 match &model.game_state.client_phase {
+                                    _ if model.game_state.spectating || !is_my_turn(model) => {
+                                        view_spectator(model)
+                                    }
                                     ClientPhase::Waiting => p!["Waiting for server..."],
                                     ClientPhase::DraftDice(dice, max) => div![
                                         h5![
@@ -213,13 +464,26 @@ fn view(model: &Model) -> Vec<Node<Msg>> {
                                                     (true, false) => None,
                                                     _ => Some(Msg::DraftDie(i)),
                                                 };
-                                                render_die(
+                                                // the tooltip shows the odds of the draft with this die added
+                                                let mut with_die = model.drafted_dice.clone();
+                                                with_die.insert(i);
+                                                render_die_with_title(
                                                     die,
-                                                    model.drafted_dice.contains(&i),
+                                                    selected,
                                                     event,
+                                                    format!(
+                                                        "{}\n{}",
+                                                        die,
+                                                        draft_odds(model, dice, &with_die)
+                                                    ),
                                                 )
                                             })
                                         ],
+                                        IF!(!model.drafted_dice.is_empty() => div![
+                                            C!["mb-2"],
+                                            style! {St::WhiteSpace => "pre-line"},
+                                            draft_odds(model, dice, &model.drafted_dice)
+                                        ]),
                                         button![
                                             C!["btn btn-primary"],
                                             ev(Ev::Click, |_| Msg::FinishDrafting),
@@ -232,15 +496,133 @@ fn view(model: &Model) -> Vec<Node<Msg>> {
                                             C!["d-flex"],
                                             dice.iter().map(|die| render_die(die, true, None))
                                         ],
-                                        actions
-                                            .iter()
-                                            .map(|action| { p![format!("{:?}", action)] })
+                                        actions.iter().enumerate().map(|(i, action)| {
+                                            button![
+                                                C![
+                                                    "btn m-1",
+                                                    if model.selected_action == Some(i) {
+                                                        "btn-primary"
+                                                    } else {
+                                                        "btn-outline-light"
+                                                    }
+                                                ],
+                                                attrs! {At::Title => action_description(action, dice)},
+                                                ev(Ev::Click, move |_| Msg::SelectAction(i)),
+                                                action_name(action),
+                                            ]
+                                        }),
+                                        model.selected_action.and_then(|i| actions.get(i)).map(
+                                            |action| {
+                                                div![
+                                                    p![C!["mt-2"], action_description(action, dice)],
+                                                    button![
+                                                        C!["btn btn-primary m-1"],
+                                                        ev(Ev::Click, |_| Msg::ConfirmAction),
+                                                        "Confirm",
+                                                    ],
+                                                    button![
+                                                        C!["btn btn-secondary m-1"],
+                                                        ev(Ev::Click, |_| Msg::CancelAction),
+                                                        "Cancel",
+                                                    ],
+                                                ]
+                                            }
+                                        ),
                                     ],
-                                    ClientPhase::SelectTarget(targets) => div![
-                                        h5![C!["card-title"], "Choose Target"],
-                                        targets
-                                            .iter()
-                                            .map(|target| { p![format!("{:?}", target)] })
+                                    ClientPhase::SelectTarget(dice, action, _) => div![
+                                        h5![
+                                            C!["card-title"],
+                                            format!("Choose Target: {}", action_name(action))
+                                        ],
+                                        div![
+                                            C!["d-flex"],
+                                            dice.iter().map(|die| render_die(die, true, None))
+                                        ],
+                                        p![action_description(action, dice)],
+                                        p![match model.selected_target.and_then(|target| {
+                                            model.game_state.combatants.get(target)
+                                        }) {
+                                            Some(target) => format!("Target: {}", target.name),
+                                            None => "Click a target in the combatants table."
+                                                .to_owned(),
+                                        }],
+                                        button![
+                                            C!["btn btn-primary m-1"],
+                                            attrs! {At::Disabled => model.selected_target.is_none().as_at_value()},
+                                            ev(Ev::Click, |_| Msg::ConfirmTarget),
+                                            "Confirm",
+                                        ],
+                                        button![
+                                            C!["btn btn-secondary m-1"],
+                                            ev(Ev::Click, |_| Msg::CancelAction),
+                                            "Back",
+                                        ],
+                                    ],
+                                    ClientPhase::ChooseReward(rewards, dice) => div![
+                                        h5![C!["card-title"], "Choose Reward"],
+                                        p!["Some rewards apply to a die from your dice pool:"],
+                                        div![
+                                            C!["d-flex"],
+                                            dice.iter().enumerate().map(|(i, die)| {
+                                                render_die(
+                                                    die,
+                                                    model.reward_die == Some(i),
+                                                    Some(Msg::SelectRewardDie(i)),
+                                                )
+                                            })
+                                        ],
+                                        rewards.iter().enumerate().map(|(i, reward)| {
+                                            button![
+                                                C!["btn btn-primary m-1"],
+                                                attrs! {At::Disabled => (reward.needs_die() && model.reward_die.is_none()).as_at_value()},
+                                                ev(Ev::Click, move |_| Msg::ChooseReward(i)),
+                                                reward.to_string(),
+                                            ]
+                                        })
+                                    ],
+                                    ClientPhase::SelectNode(floor, total_floors, nodes) => div![
+                                        h5![
+                                            C!["card-title"],
+                                            format!("Floor {} / {}", floor + 1, total_floors)
+                                        ],
+                                        p!["Choose where to go next:"],
+                                        nodes.iter().enumerate().map(|(i, node)| {
+                                            button![
+                                                C!["btn btn-primary m-1"],
+                                                ev(Ev::Click, move |_| Msg::ChooseNode(i)),
+                                                render_map_node(node),
+                                            ]
+                                        })
+                                    ],
+                                    ClientPhase::RunSummary(summary) => div![
+                                        h5![
+                                            C!["card-title"],
+                                            if summary.victory {
+                                                format!("{} cleared!", summary.campaign)
+                                            } else {
+                                                format!("Defeated in {}", summary.campaign)
+                                            }
+                                        ],
+                                        p![format!(
+                                            "Floors cleared: {} / {}",
+                                            summary.floors_cleared, summary.total_floors
+                                        )],
+                                        p![format!("Encounters won: {}", summary.encounters_won)],
+                                        p![format!("Path: {}", summary.visited.join(" \u{2192} "))],
+                                        button![
+                                            C!["btn btn-primary"],
+                                            ev(Ev::Click, |_| Msg::StartRun),
+                                            "Start New Run",
+                                        ]
+                                    ],
+                                    ClientPhase::MatchResult(result) => div![
+                                        h5![C!["card-title"], match_result_title(model, result)],
+                                        p![format!("Match in {}", result.encounter)],
+                                        button![
+                                            C!["btn btn-primary"],
+                                            ev(Ev::Click, |_| Msg::StartRun),
+                                            "Rematch",
+                                        ]
                                     ],
                                 }
                             ] // end actions card-body
@@ -251,12 +633,37 @@ fn view(model: &Model) -> Vec<Node<Msg>> {
                         // Combatants information
                         table![
                             C!["table table-dark table-striped"],
-                            thead![tr![th!["Name"], th!["HP"]]],
-                            tbody![model.game_state.combatants.iter().map(|combatant| {
-                                // combatant.
-                                tr![td![&combatant.name], td![combatant.hp],]
-                            })] // end combatants tbody
+                            thead![tr![
+                                th!["Name"],
+                                th!["HP"],
+                                th!["Dice"],
+                                th!["H Atk"],
+                                th!["Def"]
+                            ]],
+                            tbody![model.game_state.combatants.iter().enumerate().map(
+                                |(i, combatant)| {
+                                    // while choosing a target, the rows of valid targets can be clicked
+                                    let targetable = match &model.game_state.client_phase {
+                                        ClientPhase::SelectTarget(_, _, targets) => {
+                                            !model.game_state.spectating && targets.contains(&i)
+                                        }
+                                        _ => false,
+                                    };
+                                    tr![
+                                        IF!(targetable => C!["combatant-target"]),
+                                        IF!(targetable && model.selected_target == Some(i) => C!["table-primary"]),
+                                        IF!(targetable => ev(Ev::Click, move |_| Msg::SelectTarget(i))),
+                                        td![&combatant.name],
+                                        td![format!("{} / {}", combatant.hp, combatant.max_hp)],
+                                        td![render_small_dice(&combatant.pool)],
+                                        td![render_small_dice(&combatant.prepped_attack)],
+                                        td![render_small_dice(&combatant.prepped_defense)],
+                                    ]
+                                }
+                            )] // end combatants tbody
                         ], // end combatants table
+                        // Combat log
+                        view_combat_log(model),
                     ] // end second column
                 ],
                 button![
@@ -275,7 +682,7 @@ fn view(model: &Model) -> Vec<Node<Msg>> {
                         h5![C!["card-title"], "Status"],
                         p![
                             C!["card-text"],
-                            format!("Disconnected from server: {}", WS_URL)
+                            format!("Disconnected from server: {}", model.ws_url)
                         ],
                     ]
                 ]
@@ -284,11 +691,422 @@ fn view(model: &Model) -> Vec<Node<Msg>> {
     ]]
 }
 
-fn render_die(die: &Die, selected: bool, msg: Option<Msg>) -> Node<Msg> {
-    let num = match die.rolled_value {
-        Some(value) => value,
-        None => die.sides,
+fn view_profile(model: &Model, profile: &PlayerProfile) -> Node<Msg> {
+    div![
+        C!["card-text d-flex align-items-center mb-2"],
+        input![
+            C!["form-control form-control-sm w-auto me-2"],
+            attrs! {At::Value => model.name_input, At::MaxLength => 24},
+            input_ev(Ev::Input, Msg::EditName),
+        ],
+        button![
+            C!["btn btn-secondary btn-sm me-3"],
+            IF!(model.name_input == profile.name => attrs! {At::Disabled => true}),
+            ev(Ev::Click, |_| Msg::SaveName),
+            "Rename",
+        ],
+        span![format!(
+            "Rating {} ({} W / {} L), {} unlocked dice",
+            profile.rating,
+            profile.wins,
+            profile.losses,
+            profile.unlocked_dice.len()
+        )],
+    ]
+}
+
+fn view_matchmaking(model: &Model) -> Node<Msg> {
+    match &model.matchmaking {
+        Some(MatchmakingStatus::Searching) => p![
+            C!["card-text"],
+            "Looking for an opponent... ",
+            button![
+                C!["btn btn-secondary btn-sm"],
+                ev(Ev::Click, |_| Msg::CancelFindMatch),
+                "Cancel",
+            ]
+        ],
+        status => p![
+            C!["card-text"],
+            IF!(matches!(status, Some(MatchmakingStatus::TimedOut)) => "No opponent found. "),
+            button![
+                C!["btn btn-primary btn-sm"],
+                ev(Ev::Click, |_| Msg::FindMatch),
+                "Find PvP Match",
+            ]
+        ],
+    }
+}
+
+/// In a PvP match, players can only act while one of their own team has the turn.
+fn is_my_turn(model: &Model) -> bool {
+    let current = model
+        .game_state
+        .current_combatant
+        .and_then(|i| model.game_state.combatants.get(i));
+    match (model.game_state.seat, current) {
+        (Some(seat), Some(combatant)) => combatant.side == seat,
+        _ => true,
+    }
+}
+
+fn match_result_title(model: &Model, result: &MatchResult) -> String {
+    match (model.game_state.seat, result.winner) {
+        (_, None) => "The match was ended early".to_owned(),
+        (Some(seat), Some(winner)) if seat == winner => "You won!".to_owned(),
+        (Some(_), Some(_)) => "You lost".to_owned(),
+        (None, Some(winner)) => format!("Player {} won", winner + 1),
+    }
+}
+
+/// Read-only version of the current phase, for spectators and for players waiting on their opponent.
+fn view_spectator(model: &Model) -> Node<Msg> {
+    let name = model
+        .game_state
+        .current_combatant
+        .and_then(|i| model.game_state.combatants.get(i))
+        .map_or("The party", |combatant| combatant.name.as_str());
+    let (title, dice) = match &model.game_state.client_phase {
+        ClientPhase::Waiting => ("Waiting for server...".to_owned(), None),
+        ClientPhase::DraftDice(dice, max) => (
+            format!("{} is drafting up to {} dice", name, max),
+            Some(dice),
+        ),
+        ClientPhase::SelectAction(dice, _) => {
+            (format!("{} is choosing an action", name), Some(dice))
+        }
+        ClientPhase::SelectTarget(dice, action, _) => (
+            format!("{} is choosing a target for {}", name, action_name(action)),
+            Some(dice),
+        ),
+        ClientPhase::ChooseReward(_, dice) => {
+            ("The party is choosing a reward".to_owned(), Some(dice))
+        }
+        ClientPhase::SelectNode(floor, total_floors, _) => (
+            format!(
+                "The party is choosing where to go on floor {} / {}",
+                floor + 1,
+                total_floors
+            ),
+            None,
+        ),
+        ClientPhase::RunSummary(summary) => (
+            if summary.victory {
+                format!("{} cleared!", summary.campaign)
+            } else {
+                format!("Defeated in {}", summary.campaign)
+            },
+            None,
+        ),
+        ClientPhase::MatchResult(result) => (match_result_title(model, result), None),
     };
+    div![
+        h5![C!["card-title"], title],
+        dice.map(|dice| div![
+            C!["d-flex"],
+            dice.iter().map(|die| render_die(die, false, None))
+        ]),
+    ]
+}
+
+fn render_map_node(node: &MapNode) -> String {
+    match &node.kind {
+        MapNodeKind::Encounter(enemies) => format!("{}: fight {}", node.name, enemies.join(", ")),
+        MapNodeKind::Rest(heal) => format!("{}: rest and heal {} HP", node.name, heal),
+        MapNodeKind::Reward => format!("{}: pick a reward", node.name),
+    }
+}
+
+/// Expected damage of attacking with the drafted dice, and the chance to kill each target with it.
+fn draft_odds(model: &Model, dice: &[Die], drafted: &HashSet<usize>) -> String {
+    let drafted: Vec<Die> = dice
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| drafted.contains(i))
+        .map(|(_, die)| die.clone())
+        .collect();
+    let combatants = &model.game_state.combatants;
+    let current = model.game_state.current_combatant;
+
+    let mut attacks = vec![("Light attack", DamageDistribution::of_attack(&drafted))];
+    // a heavy attack adds in the dice that were already prepped
+    if let Some(attacker) = current.and_then(|n| combatants.get(n)) {
+        if !attacker.prepped_attack.is_empty() {
+            let mut heavy_dice = drafted.clone();
+            heavy_dice.extend(attacker.prepped_attack.iter().cloned());
+            attacks.push(("Heavy attack", DamageDistribution::of_attack(&heavy_dice)));
+        }
+    }
+
+    let mut lines = vec![];
+    for (name, damage) in attacks {
+        lines.push(format!(
+            "{}: {:.1} expected damage",
+            name,
+            damage.expected()
+        ));
+        for (n, target) in combatants.iter().enumerate() {
+            if Some(n) != current && target.hp > 0 {
+                lines.push(format!(
+                    "  {:.0}% to kill {}",
+                    damage.chance_to_kill(target.hp, defense_block(&target.prepped_defense)) * 100.,
+                    target.name
+                ));
+            }
+        }
+    }
+    lines.join("\n")
+}
+
+fn action_name(action: &ClientAction) -> &'static str {
+    match action {
+        ClientAction::LightAttack => "Light Attack",
+        ClientAction::PrepHeavyAttack => "Prep Heavy Atk",
+        ClientAction::HeavyAttack(_) => "Heavy Attack",
+        ClientAction::Defend => "Defend",
+    }
+}
+
+fn action_description(action: &ClientAction, rolled: &[Die]) -> String {
+    match action {
+        ClientAction::LightAttack => format!(
+            "Attack a target with the rolled dice for {} damage.",
+            attack_damage(rolled)
+        ),
+        ClientAction::PrepHeavyAttack => format!(
+            "Save the rolled dice ({} damage) to add to a heavy attack on a later turn.",
+            attack_damage(rolled)
+        ),
+        ClientAction::HeavyAttack(prepped) => {
+            let dice: Vec<Die> = rolled.iter().chain(prepped.iter()).cloned().collect();
+            format!(
+                "Attack a target with the rolled dice and {} prepped dice for {} damage.",
+                prepped.len(),
+                attack_damage(&dice)
+            )
+        }
+        ClientAction::Defend => format!(
+            "Block up to {} damage until your next turn.",
+            defense_block(rolled)
+        ),
+    }
+}
+
+fn view_combat_log(model: &Model) -> Node<Msg> {
+    // every combatant that shows up in the log can be filtered on, even if they're dead and gone
+    let mut names: Vec<&str> = vec![];
+    for entry in model.game_state.combat_log.iter() {
+        for name in log_entry_combatants(entry) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    let shown = |entry: &LogEntry| match &model.log_filter {
+        Some(filter) => log_entry_combatants(entry).contains(&filter.as_str()),
+        None => true,
+    };
+
+    // entries are grouped under the node or turn they happened in
+    let mut groups: Vec<(Option<&LogEntry>, Vec<&LogEntry>)> = vec![];
+    for entry in model.game_state.combat_log.iter() {
+        match entry {
+            LogEntry::EnteredNode(_) | LogEntry::TurnStarted { .. } => {
+                groups.push((Some(entry), vec![]))
+            }
+            _ => match groups.last_mut() {
+                Some((_, entries)) => entries.push(entry),
+                None => groups.push((None, vec![entry])),
+            },
+        }
+    }
+
+    div![
+        C!["card text-dark bg-light"],
+        div![
+            C!["card-body"],
+            h5![C!["card-title"], "Combat Log"],
+            select![
+                C!["form-select form-select-sm mb-2"],
+                input_ev(Ev::Change, Msg::FilterLog),
+                option![attrs! {At::Value => ""}, "All combatants"],
+                names.iter().map(|name| {
+                    option![
+                        attrs! {
+                            At::Value => name,
+                            At::Selected => (model.log_filter.as_deref() == Some(*name)).as_at_value(),
+                        },
+                        name
+                    ]
+                }),
+            ],
+            div![
+                C!["combat-log"],
+                el_ref(&model.log_panel),
+                groups.into_iter().filter_map(|(header, entries)| {
+                    let entries: Vec<&LogEntry> =
+                        entries.into_iter().filter(|entry| shown(entry)).collect();
+                    if entries.is_empty() && !header.is_some_and(shown) {
+                        return None;
+                    }
+                    Some(div![
+                        C!["combat-log-group"],
+                        match header {
+                            Some(LogEntry::EnteredNode(node)) => {
+                                h6![C!["combat-log-node"], node]
+                            }
+                            Some(LogEntry::TurnStarted { round, combatant }) => {
+                                h6![format!("Round {}: {}'s turn", round, combatant)]
+                            }
+                            _ => empty![],
+                        },
+                        entries
+                            .into_iter()
+                            .map(|entry| div![C!["combat-log-entry"], render_log_entry(entry)])
+                    ])
+                })
+            ]
+        ]
+    ]
+}
+
+/// Names of the combatants a log entry is about, for filtering the log.
+fn log_entry_combatants(entry: &LogEntry) -> Vec<&str> {
+    match entry {
+        LogEntry::EnteredNode(_)
+        | LogEntry::PartyDefeated
+        | LogEntry::MatchWon { .. }
+        | LogEntry::BattleEnded => vec![],
+        LogEntry::Attack {
+            attacker, target, ..
+        } => vec![attacker, target],
+        LogEntry::TurnStarted { combatant, .. }
+        | LogEntry::Drafted { combatant, .. }
+        | LogEntry::Rolled { combatant, .. }
+        | LogEntry::Exploded { combatant, .. }
+        | LogEntry::CriticalHit { combatant, .. }
+        | LogEntry::Prepped { combatant, .. }
+        | LogEntry::TimedOut { combatant }
+        | LogEntry::DieBroke { combatant, .. }
+        | LogEntry::Death { combatant }
+        | LogEntry::BattleWon { combatant }
+        | LogEntry::Reward { combatant, .. } => vec![combatant],
+    }
+}
+
+fn render_log_entry(entry: &LogEntry) -> Vec<Node<Msg>> {
+    let text = |s: String| span![s];
+    let dice = render_small_dice;
+    let damage = |n: usize| span![C!["text-danger fw-bold"], n.to_string()];
+    match entry {
+        LogEntry::EnteredNode(node) => vec![text(format!("The party entered {}", node))],
+        LogEntry::TurnStarted { round, combatant } => {
+            vec![text(format!("Round {}: {}'s turn", round, combatant))]
+        }
+        LogEntry::Drafted { combatant, die, .. } => vec![
+            text(format!("{} drafted ", combatant)),
+            dice(std::slice::from_ref(die)),
+        ],
+        LogEntry::Rolled {
+            combatant,
+            dice: rolled,
+            ..
+        } => {
+            vec![text(format!("{} rolled ", combatant)), dice(rolled)]
+        }
+        LogEntry::Exploded {
+            combatant,
+            die,
+            face,
+            ..
+        } => vec![
+            text(format!("{}'s ", combatant)),
+            dice(std::slice::from_ref(die)),
+            text(format!(" exploded into {}", face)),
+        ],
+        LogEntry::CriticalHit {
+            combatant,
+            die,
+            damage: n,
+        } => vec![
+            text(format!("{}'s ", combatant)),
+            dice(std::slice::from_ref(die)),
+            text(" critical hit for ".to_owned()),
+            damage(*n),
+        ],
+        LogEntry::Attack {
+            attacker,
+            target,
+            heavy,
+            damage: n,
+            blocked,
+        } => vec![
+            text(format!(
+                "{} {} attack did ",
+                attacker,
+                if *heavy { "heavy" } else { "light" }
+            )),
+            damage(*n),
+            text(format!(" damage to {} ({} blocked)", target, blocked)),
+        ],
+        LogEntry::Prepped {
+            combatant,
+            prep,
+            dice: prepped,
+            ..
+        } => vec![
+            text(match prep {
+                Prep::HeavyAttack => format!("{} prepped for heavy attack ", combatant),
+                Prep::Defense => format!("{} prepped for defense ", combatant),
+            }),
+            dice(prepped),
+        ],
+        LogEntry::TimedOut { combatant } => vec![text(format!(
+            "{} ran out of time, so their turn was played for them",
+            combatant
+        ))],
+        LogEntry::DieBroke { combatant, die } => vec![
+            text(format!("{}'s fragile ", combatant)),
+            dice(std::slice::from_ref(die)),
+            text(" broke".to_owned()),
+        ],
+        LogEntry::Death { combatant } => vec![span![
+            C!["text-danger fw-bold"],
+            format!("{} died", combatant)
+        ]],
+        LogEntry::BattleWon { combatant } => vec![text(format!("{} won the battle", combatant))],
+        LogEntry::PartyDefeated => vec![text("The party was defeated".to_owned())],
+        LogEntry::MatchWon { seat } => vec![text(format!("Player {} won the match", seat + 1))],
+        LogEntry::BattleEnded => vec![text("The battle was ended early".to_owned())],
+        LogEntry::Reward {
+            combatant,
+            reward,
+            die,
+        } => vec![
+            text(match reward {
+                Reward::NewDie(_) => format!("{} added ", combatant),
+                Reward::UpgradeSides(_) => format!("{} upgraded a die to ", combatant),
+                Reward::ChangeColor(_) => format!("{} recolored a die to ", combatant),
+                Reward::RemoveDie => format!("{} removed ", combatant),
+            }),
+            dice(std::slice::from_ref(die)),
+        ],
+    }
+}
+
+/// Dice small enough to show inline with text.
+fn render_small_dice(dice: &[Die]) -> Node<Msg> {
+    span![
+        C!["dice-small"],
+        dice.iter().map(|die| render_die(die, false, None))
+    ]
+}
+
+fn render_die(die: &Die, selected: bool, msg: Option<Msg>) -> Node<Msg> {
+    render_die_with_title(die, selected, msg, die.to_string())
+}
+
+fn render_die_with_title(die: &Die, selected: bool, msg: Option<Msg>, title: String) -> Node<Msg> {
     let mut die_classes = vec![
         "face",
         match die.color {
@@ -302,13 +1120,58 @@ fn render_die(die: &Die, selected: bool, msg: Option<Msg>) -> Node<Msg> {
     if selected {
         die_classes.push("face-selected");
     }
+    let rules: Vec<&str> = vec![
+        (die.rules.exploding, "exploding"),
+        (die.rules.critical, "critical"),
+        (die.rules.fragile, "fragile"),
+    ]
+    .into_iter()
+    .filter(|(enabled, _)| *enabled)
+    .map(|(_, rule)| rule)
+    .collect();
     div![
-        C![die_classes],
-        ev(Ev::Click, |_| msg),
-        (0..num).map(|_| span![C!["pip"]])
+        C!["die"],
+        div![
+            C![die_classes],
+            attrs! {At::Title => title},
+            ev(Ev::Click, |_| msg),
+            match die.rolled_value {
+                Some(face) => render_face(face),
+                None if die.is_hidden() => vec![span![C!["face-label"], "?"]],
+                None if die.is_standard() => render_face(Face::Number(die.sides())),
+                // unrolled custom dice list all of their faces
+                None => vec![span![
+                    C!["face-label face-label-small"],
+                    die.faces
+                        .iter()
+                        .map(|face| face.to_string())
+                        .collect::<Vec<String>>()
+                        .join(",")
+                ]],
+            }
+        ],
+        IF!(!die.exploded.is_empty() => div![
+            C!["die-note die-exploded"],
+            die.exploded
+                .iter()
+                .map(|face| format!("+{}", face))
+                .collect::<String>()
+        ]),
+        IF!(!rules.is_empty() => div![C!["die-note"], rules.join(" ")]),
     ]
 }
 
+fn render_face(face: Face) -> Vec<Node<Msg>> {
+    match face {
+        // the pip layout only has room for up to 6 pips
+        Face::Number(n) if n <= 6 => (0..n).map(|_| span![C!["pip"]]).collect(),
+        Face::Number(n) => vec![span![C!["face-label"], n.to_string()]],
+        Face::Shield(n) => vec![span![C!["face-label"], format!("\u{1f6e1}{}", n)]],
+        Face::Crit => vec![span![C!["face-label"], "\u{2738}"]],
+        Face::Blank => vec![],
+    }
+}
+
 // ------ ------
 //     Start
 // ------ ------
@@ -0,0 +1,168 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use tracing::warn;
+
+// in the data directory, see `Config::token_secret_file`
+pub const SECRET_FILE: &str = "token_secret";
+
+/// Signs and checks guest player tokens, which look like `<player id>.<signature>`. The secret comes
+/// from the `TOKEN_SECRET` environment variable, or else is generated once and kept in the given file,
+/// so tokens stay valid across server restarts. An empty secret would let anyone sign tokens, so it's
+/// never used.
+pub struct TokenSigner {
+    secret: Vec<u8>,
+}
+
+impl TokenSigner {
+    pub fn load(secret_file: &Path) -> Self {
+        TokenSigner::from_secret(env::var("TOKEN_SECRET").ok(), secret_file)
+    }
+
+    /// Like `load`, given the value of `TOKEN_SECRET` if it's set.
+    fn from_secret(secret: Option<String>, secret_file: &Path) -> Self {
+        match secret {
+            Some(secret) if !secret.is_empty() => {
+                return TokenSigner {
+                    secret: secret.into_bytes(),
+                }
+            }
+            Some(_) => warn!("Ignoring empty TOKEN_SECRET"),
+            None => {}
+        }
+        let secret = match fs::read_to_string(secret_file) {
+            Ok(secret) if !secret.trim().is_empty() => secret.trim().to_owned(),
+            _ => {
+                let secret = to_hex(&rand::random::<[u8; 32]>());
+                if let Err(err) = write_secret(secret_file, &secret) {
+                    warn!(
+                        %err,
+                        "Could not save token secret, tokens won't survive a restart"
+                    );
+                }
+                secret
+            }
+        };
+        TokenSigner {
+            secret: secret.into_bytes(),
+        }
+    }
+
+    pub fn issue(&self, player: &str) -> String {
+        format!("{}.{}", player, to_hex(&self.sign(player)))
+    }
+
+    /// The player id the token was issued for, if its signature checks out.
+    pub fn verify(&self, token: &str) -> Option<String> {
+        let (player, signature) = token.split_once('.')?;
+        let expected = to_hex(&self.sign(player));
+        if secrets_match(signature, &expected) {
+            Some(player.to_owned())
+        } else {
+            None
+        }
+    }
+
+    /// HMAC-SHA256 of the player id.
+    fn sign(&self, player: &str) -> Vec<u8> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC takes keys of any length");
+        mac.update(player.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+}
+
+/// Fresh random id for a guest player.
+pub fn new_player_id() -> String {
+    format!("guest-{}", to_hex(&rand::random::<[u8; 8]>()))
+}
+
+/// Compare every byte, so the time taken doesn't give away how much of a secret matched.
+pub fn secrets_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn write_secret(path: &Path, secret: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, secret)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::process;
+
+    /// Secret file path that no other test uses, without the file.
+    fn secret_file(test: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("dice-combat-secret-{}-{}", process::id(), test));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn signer(secret: &str) -> TokenSigner {
+        TokenSigner::from_secret(Some(secret.to_owned()), &secret_file("unused"))
+    }
+
+    #[test]
+    fn issued_tokens_verify() {
+        let signer = signer("secret");
+        let token = signer.issue("guest-1");
+        assert!(token.starts_with("guest-1."));
+        assert_eq!(signer.verify(&token), Some("guest-1".to_owned()));
+    }
+
+    #[test]
+    fn tampered_tokens_dont_verify() {
+        let signer = signer("secret");
+        let token = signer.issue("guest-1");
+        let (_, signature) = token.split_once('.').unwrap();
+        let flipped = if signature.starts_with('0') { "1" } else { "0" };
+
+        for tampered in [
+            format!("guest-2.{}", signature),
+            format!("guest-1.{}{}", flipped, &signature[1..]),
+            format!("guest-1.{}", &signature[1..]),
+            "guest-1".to_owned(),
+            String::new(),
+        ] {
+            assert_eq!(signer.verify(&tampered), None, "{}", tampered);
+        }
+    }
+
+    #[test]
+    fn tokens_from_another_secret_dont_verify() {
+        let token = signer("secret").issue("guest-1");
+        assert_eq!(signer("other secret").verify(&token), None);
+    }
+
+    #[test]
+    fn an_empty_token_secret_is_ignored() {
+        let path = secret_file("empty");
+        let signer = TokenSigner::from_secret(Some(String::new()), &path);
+        let unsigned = TokenSigner { secret: vec![] };
+        assert_eq!(signer.verify(&unsigned.issue("guest-1")), None);
+
+        // the generated secret is used instead, and kept so tokens stay valid after a restart
+        let restarted = TokenSigner::from_secret(None, &path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            restarted.verify(&signer.issue("guest-1")),
+            Some("guest-1".to_owned())
+        );
+    }
+}
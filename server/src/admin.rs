@@ -0,0 +1,97 @@
+use crate::accounts::secrets_match;
+use crate::campaign::Run;
+use crate::combat_state::CombatState;
+use crate::room::RoomSettings;
+use crate::save::SaveFile;
+use crate::websocket::Server;
+use serde::Serialize;
+use specs::WorldExt;
+use std::env;
+use tracing::{error, info};
+use ws::{Request, Response};
+
+#[derive(Serialize)]
+struct RoomInfo {
+    code: String,
+    settings: RoomSettings,
+    clients: usize,
+    round: usize,
+    phase: String,
+}
+
+/// Admin endpoints, for looking into and unsticking rooms:
+/// - `GET /admin/rooms` lists every room
+/// - `GET /admin/rooms/<room code>` dumps the room's whole battle, in the save file format
+/// - `POST /admin/rooms/<room code>/end` ends the room's battle right away, without a winner
+///
+/// Requests need an `Authorization: Bearer <token>` header matching the `ADMIN_TOKEN` environment
+/// variable. Without it set, the admin endpoints are turned off.
+pub fn handle(server: &mut Server, req: &Request, path: &str) -> Response {
+    let admin_token = match env::var("ADMIN_TOKEN") {
+        Ok(token) if !token.is_empty() => token,
+        _ => return text_response(404, "Not Found", "Not found"),
+    };
+    let authorized = req
+        .header("authorization")
+        .and_then(|value| std::str::from_utf8(value).ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| secrets_match(token.trim(), &admin_token));
+    if !authorized {
+        return text_response(401, "Unauthorized", "Missing or invalid admin token");
+    }
+
+    let parts: Vec<&str> = path.trim_end_matches('/').split('/').skip(2).collect();
+    match (req.method(), &parts[..]) {
+        ("GET", ["rooms"]) => {
+            let mut rooms: Vec<RoomInfo> = server
+                .rooms
+                .values()
+                .map(|room| {
+                    let combat_state = room.world.read_resource::<CombatState>();
+                    RoomInfo {
+                        code: room.code.clone(),
+                        settings: *room.world.read_resource::<RoomSettings>(),
+                        clients: room.clients.len(),
+                        round: combat_state.round,
+                        phase: format!("{:?}", room.world.read_resource::<Run>().phase),
+                    }
+                })
+                .collect();
+            rooms.sort_by(|a, b| a.code.cmp(&b.code));
+            json_response(&rooms)
+        }
+        ("GET", ["rooms", code]) => match server.rooms.get(*code) {
+            Some(room) => json_response(&SaveFile::from_world(&room.world)),
+            None => text_response(404, "Not Found", "No such room"),
+        },
+        ("POST", ["rooms", code, "end"]) => {
+            let room = match server.rooms.get_mut(*code) {
+                Some(room) => room,
+                None => return text_response(404, "Not Found", "No such room"),
+            };
+            if !room.force_end() {
+                return text_response(409, "Conflict", "The battle is already over");
+            }
+            info!(room = %room.code, "Admin ended the battle");
+            if let Err(err) = room.save() {
+                error!(room = %room.code, %err, "Could not save room");
+            }
+            room.broadcast();
+            room.schedule_turn_timer();
+            text_response(200, "OK", "Battle ended")
+        }
+        _ => text_response(404, "Not Found", "Not found"),
+    }
+}
+
+pub fn json_response<T: Serialize>(value: &T) -> Response {
+    let mut response = Response::new(200, "OK", serde_json::to_vec(value).unwrap());
+    response
+        .headers_mut()
+        .push(("Content-Type".to_owned(), b"application/json".to_vec()));
+    response
+}
+
+pub fn text_response(status: u16, reason: &str, body: &str) -> Response {
+    Response::new(status, reason, body.as_bytes().to_vec())
+}
@@ -0,0 +1,67 @@
+use crate::combat_state::*;
+use crate::components::*;
+use crate::probability::DamageDistribution;
+use crate::shared::ClientMessage;
+use specs::{World, WorldExt};
+use std::cmp::Ordering;
+
+/// Simple policy for playing a combatant's turn, used when a player runs out of time.
+/// Returns the message the player would have sent for the current phase, if there is one.
+pub fn next_message(world: &World) -> Option<ClientMessage> {
+    let combat_state = world.read_resource::<CombatState>();
+    let current_entity = combat_state.combatants[combat_state.current_character];
+    match &combat_state.current_phase {
+        // draft the dice that do the most damage on average
+        CombatPhase::Drafting => {
+            let dice_pools = world.read_storage::<DicePool>();
+            let dice_pool = dice_pools.get(current_entity)?;
+            let mut choices: Vec<(usize, f64)> = dice_pool
+                .available
+                .iter()
+                .enumerate()
+                .map(|(i, die)| {
+                    let expected =
+                        DamageDistribution::of_attack(std::slice::from_ref(die)).expected();
+                    (i, expected)
+                })
+                .collect();
+            choices.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+            Some(ClientMessage::FinishDrafting(
+                choices
+                    .into_iter()
+                    .take(dice_pool.max_draft_amount)
+                    .map(|(i, _)| i)
+                    .collect(),
+            ))
+        }
+        // always attack if possible, preferring a heavy attack once it's prepped
+        CombatPhase::SelectAction(possible_actions) if !possible_actions.is_empty() => {
+            let preference = |action: &CombatAction| match action {
+                CombatAction::HeavyAttack(_) => 0,
+                CombatAction::LightAttack(_) => 1,
+                CombatAction::PrepHeavyAttack => 2,
+                CombatAction::Defend => 3,
+            };
+            let (n, _) = possible_actions
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, (_, action))| preference(action))?;
+            Some(ClientMessage::ChooseAction(n))
+        }
+        // attack the weakest combatant on the other side
+        CombatPhase::SelectTarget(_) => {
+            let healths = world.read_storage::<Health>();
+            let enemies = world.read_storage::<Enemy>();
+            let is_enemy = enemies.contains(current_entity);
+            (0..combat_state.combatants.len())
+                .filter(|n| {
+                    let combatant = combat_state.combatants[*n];
+                    enemies.contains(combatant) != is_enemy
+                        && healths.get(combatant).is_some_and(|health| health.hp > 0)
+                })
+                .min_by_key(|n| healths.get(combat_state.combatants[*n]).unwrap().hp)
+                .map(ClientMessage::ChooseTarget)
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,236 @@
+use crate::combat_state::*;
+use crate::combatant::*;
+use crate::components::*;
+use crate::events::TurnStarted;
+use crate::log::CombatLog;
+use crate::rng::DiceRng;
+use crate::shared::{LogEntry, MapNode, MapNodeKind, RunSummary};
+use crate::systems::generate_rewards;
+use serde::{Deserialize, Serialize};
+use specs::shrev::EventChannel;
+use specs::{Entity, World, WorldExt};
+use std::fs;
+
+pub const CAMPAIGN_FILE: &str = "assets/campaign.json";
+
+/// Data file definition of a campaign: the starting party, the enemies that can be fought,
+/// and the floors of the map. Each floor offers a choice between a few nodes.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Campaign {
+    pub name: String,
+    pub party: Vec<CombatantDef>,
+    pub enemies: Vec<CombatantDef>,
+    pub floors: Vec<Vec<MapNode>>,
+}
+
+impl Campaign {
+    /// Read and validate a campaign file. This happens once when the server starts, so a bad file
+    /// stops the server there rather than failing whenever a room is created.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let data = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read campaign file {}: {}", path, err))?;
+        let campaign: Campaign = serde_json::from_str(&data)
+            .map_err(|err| format!("Could not parse campaign file {}: {}", path, err))?;
+        campaign
+            .validate()
+            .map_err(|err| format!("Invalid campaign file {}: {}", path, err))?;
+        Ok(campaign)
+    }
+
+    /// Check everything a run relies on: a party, dice that can be built, and enemies for every encounter.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.party.is_empty() {
+            return Err("the party is empty".to_owned());
+        }
+        for def in self.party.iter().chain(self.enemies.iter()) {
+            def.validate()?;
+        }
+        for node in self.floors.iter().flatten() {
+            if let MapNodeKind::Encounter(enemy_names) = &node.kind {
+                if let Some(name) = enemy_names.iter().find(|name| self.enemy(name).is_none()) {
+                    return Err(format!("{} has no enemy named {}", node.name, name));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn enemy(&self, name: &str) -> Option<&CombatantDef> {
+        self.enemies.iter().find(|enemy| enemy.name == name)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum RunPhase {
+    SelectNode,
+    InNode,
+    Finished(bool), // whether the party made it through every floor
+    Ended,          // stopped early by an admin, without a winner
+}
+
+/// Progress through a campaign. The party entities (and their Health and DicePool) live on between nodes.
+pub struct Run {
+    pub campaign: Campaign,
+    pub party: Vec<Entity>,
+    pub phase: RunPhase,
+    pub floor: usize,
+    pub encounters_won: usize,
+    pub visited: Vec<String>,
+}
+
+impl Run {
+    pub fn current_nodes(&self) -> &[MapNode] {
+        self.campaign
+            .floors
+            .get(self.floor)
+            .map_or(&[], |nodes| &nodes[..])
+    }
+
+    pub fn summary(&self, victory: bool) -> RunSummary {
+        RunSummary {
+            campaign: self.campaign.name.clone(),
+            victory,
+            floors_cleared: self.floor,
+            total_floors: self.campaign.floors.len(),
+            encounters_won: self.encounters_won,
+            visited: self.visited.clone(),
+        }
+    }
+}
+
+/// Start a fresh run of the campaign, throwing away everything from the previous run.
+pub fn start_run(world: &mut World, campaign: Campaign) {
+    world.delete_all();
+    world.maintain();
+
+    let party: Vec<Entity> = campaign
+        .party
+        .iter()
+        .map(|def| create_combatant(world, def, false))
+        .collect();
+    world.insert(CombatState::starting_in(
+        party.clone(),
+        CombatPhase::EncounterOver,
+    ));
+    world.insert(CombatLog {
+        ..Default::default()
+    });
+    world.insert(Run {
+        campaign,
+        party,
+        phase: RunPhase::SelectNode,
+        floor: 0,
+        encounters_won: 0,
+        visited: vec![],
+    });
+}
+
+/// Enter the chosen node on the current floor of the map.
+pub fn enter_node(world: &mut World, n: usize) {
+    let (node, enemies) = {
+        let run = world.read_resource::<Run>();
+        let node = match (run.phase, run.current_nodes().get(n)) {
+            (RunPhase::SelectNode, Some(node)) => node.clone(),
+            _ => return,
+        };
+        let enemies: Vec<CombatantDef> = match &node.kind {
+            MapNodeKind::Encounter(enemy_names) => enemy_names
+                .iter()
+                // validating the campaign made sure every enemy exists
+                .filter_map(|name| run.campaign.enemy(name).cloned())
+                .collect(),
+            _ => vec![],
+        };
+        (node, enemies)
+    };
+    let party = living_party(world);
+
+    let combat_state = match &node.kind {
+        MapNodeKind::Encounter(_) => {
+            let mut combatants = party.clone();
+            for def in enemies.iter() {
+                combatants.push(create_combatant(world, def, true));
+            }
+            CombatState::new(combatants)
+        }
+        MapNodeKind::Rest(heal) => {
+            let mut healths = world.write_storage::<Health>();
+            for member in party.iter() {
+                if let Some(health) = healths.get_mut(*member) {
+                    health.hp = (health.hp + heal).min(health.max_hp);
+                }
+            }
+            CombatState::starting_in(party.clone(), CombatPhase::EncounterOver)
+        }
+        MapNodeKind::Reward => {
+            // the party is first in the list of combatants, so its first living member picks the reward
+            let rewards = generate_rewards(&mut world.write_resource::<DiceRng>());
+            CombatState::starting_in(party.clone(), CombatPhase::Reward(0, rewards))
+        }
+    };
+    if let MapNodeKind::Encounter(_) = node.kind {
+        world
+            .write_resource::<EventChannel<TurnStarted>>()
+            .single_write(TurnStarted {
+                round: combat_state.round,
+                combatant: party[0],
+            });
+    }
+    world.insert(combat_state);
+
+    world
+        .write_resource::<CombatLog>()
+        .add(LogEntry::EnteredNode(node.name.clone()));
+    let mut run = world.write_resource::<Run>();
+    run.visited.push(node.name);
+    run.phase = RunPhase::InNode;
+}
+
+/// Leave the current node once it's over: remove its enemies, and move on to the next floor
+/// (or end the run if the party is dead or the last floor is done). Returns false if not in a node.
+pub fn finish_node(world: &mut World) -> bool {
+    if world.read_resource::<Run>().phase != RunPhase::InNode {
+        return false;
+    }
+
+    let combatants = world.read_resource::<CombatState>().combatants.clone();
+    let mut fought = false;
+    for combatant in combatants {
+        if world.read_storage::<Enemy>().contains(combatant) {
+            world.delete_entity(combatant).unwrap();
+            fought = true;
+        }
+    }
+    world.maintain();
+
+    let party_alive = !living_party(world).is_empty();
+    let mut run = world.write_resource::<Run>();
+    if party_alive {
+        run.floor += 1;
+        if fought {
+            run.encounters_won += 1;
+        }
+    }
+    run.phase = if !party_alive {
+        RunPhase::Finished(false)
+    } else if run.floor >= run.campaign.floors.len() {
+        RunPhase::Finished(true)
+    } else {
+        RunPhase::SelectNode
+    };
+
+    let combat_state = CombatState::starting_in(run.party.clone(), CombatPhase::EncounterOver);
+    drop(run);
+    world.insert(combat_state);
+    true
+}
+
+fn living_party(world: &World) -> Vec<Entity> {
+    let run = world.read_resource::<Run>();
+    let healths = world.read_storage::<Health>();
+    run.party
+        .iter()
+        .copied()
+        .filter(|member| healths.get(*member).is_some_and(|health| health.hp > 0))
+        .collect()
+}
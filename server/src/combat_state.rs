@@ -1,43 +1,267 @@
 use crate::shared::*;
+use serde::{Deserialize, Serialize};
 use specs::Entity;
+use tracing::debug;
 
-#[derive(Debug, Clone)]
+// Combatants are referred to by their position in CombatState::combatants, so that phases can be saved.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum CombatPhase {
     Drafting,
     Roll,
     SelectAction(Vec<(String, CombatAction)>),
-    Action(CombatAction),
+    SelectTarget(CombatAction), // a targeted action, waiting for its target to be chosen
+    Action(CombatAction),       // an action with everything it needs, to be resolved
+    Reward(usize, Vec<Reward>), // all enemies are dead, and the given party member picks a reward
+    EncounterOver,
+}
+
+/// Everything that moves a battle from one phase to another. Each combatant's turn goes
+/// Drafting → Roll → SelectAction → SelectTarget → Action → Drafting (of the next combatant's turn), where
+/// untargeted actions skip SelectTarget, and a targeted action can be cancelled back to SelectAction. Once
+/// either side is dead, the battle moves on to Reward (if the party won something) and then EncounterOver.
+#[derive(Debug, Clone)]
+pub enum Transition {
+    FinishDrafting,                           // Drafting → Roll
+    Rolled, // Roll → SelectAction, before the possible actions are known
+    ListActions(Vec<(String, CombatAction)>), // SelectAction → SelectAction, with the possible actions
+    ChooseAction(usize), // SelectAction → SelectTarget or Action, by position in the possible actions
+    ChooseTarget(usize), // SelectTarget → Action
+    CancelAction,        // SelectTarget → SelectAction
+    EndTurn,             // Action → Drafting, once the action has been resolved
+    WinBattle(usize, Vec<Reward>), // any phase of a turn → Reward, for the given party member
+    EndEncounter,        // any phase → EncounterOver
+}
+
+impl CombatPhase {
+    /// The phase the transition leads to, or None if it isn't allowed from this phase.
+    pub fn next(&self, transition: Transition) -> Option<CombatPhase> {
+        use CombatAction::*;
+        use CombatPhase::*;
+        match (self, transition) {
+            (Drafting, Transition::FinishDrafting) => Some(Roll),
+            (Roll, Transition::Rolled) => Some(SelectAction(vec![])),
+            (SelectAction(actions), Transition::ListActions(listed))
+                if actions.is_empty() && !listed.is_empty() =>
+            {
+                Some(SelectAction(listed))
+            }
+            (SelectAction(actions), Transition::ChooseAction(n)) => {
+                actions.get(n).map(|(_, action)| match action {
+                    LightAttack(None) | HeavyAttack(None) => SelectTarget(*action),
+                    _ => Action(*action),
+                })
+            }
+            (SelectTarget(LightAttack(None)), Transition::ChooseTarget(target)) => {
+                Some(Action(LightAttack(Some(target))))
+            }
+            (SelectTarget(HeavyAttack(None)), Transition::ChooseTarget(target)) => {
+                Some(Action(HeavyAttack(Some(target))))
+            }
+            (SelectTarget(_), Transition::CancelAction) => Some(SelectAction(vec![])),
+            (
+                Action(LightAttack(Some(_)))
+                | Action(HeavyAttack(Some(_)))
+                | Action(PrepHeavyAttack)
+                | Action(Defend),
+                Transition::EndTurn,
+            ) => Some(Drafting),
+            (
+                Drafting | Roll | SelectAction(_) | SelectTarget(_) | Action(_),
+                Transition::WinBattle(winner, rewards),
+            ) => Some(Reward(winner, rewards)),
+            (EncounterOver, Transition::EndEncounter) => None,
+            (_, Transition::EndEncounter) => Some(EncounterOver),
+            _ => None,
+        }
+    }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum CombatAction {
-    LightAttack(Option<Entity>),
+    LightAttack(Option<usize>),
     PrepHeavyAttack,
-    HeavyAttack(Option<Entity>),
+    HeavyAttack(Option<usize>),
     Defend,
 }
 
+impl CombatAction {
+    /// Position of the action's target in the combatants, once it has one.
+    pub fn target(&self) -> Option<usize> {
+        match self {
+            CombatAction::LightAttack(target) | CombatAction::HeavyAttack(target) => *target,
+            CombatAction::PrepHeavyAttack | CombatAction::Defend => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct CombatState {
+    pub round: usize, // every living combatant gets one turn per round
     pub current_character: usize,
     pub combatants: Vec<Entity>,
-    pub current_phase: CombatPhase,
+    pub current_phase: CombatPhase, // only changed through `transition`, once the battle has started
     // Client-side version of the current state and world, materialized by MaterializeSystem
     pub materialized_state: ClientGameState,
 }
 
 impl CombatState {
     pub fn new(combatants: Vec<Entity>) -> Self {
+        CombatState::starting_in(combatants, CombatPhase::Drafting)
+    }
+
+    /// A battle that starts in the given phase, e.g. a node without any fighting that goes straight to its reward.
+    pub fn starting_in(combatants: Vec<Entity>, phase: CombatPhase) -> Self {
         CombatState {
+            round: 1,
             current_character: 0,
             combatants,
-            current_phase: CombatPhase::Drafting,
+            current_phase: phase,
             materialized_state: ClientGameState {
                 // These values don't matter, they will get immediately replaced by MaterializeSystem
                 client_phase: ClientPhase::Waiting,
                 combatants: vec![],
+                current_combatant: None,
+                turn_time_left: None,
+                spectating: false,
+                seat: None,
                 combat_log: vec![],
             },
         }
     }
+
+    /// Move on to the next phase, if the transition is allowed from the current one.
+    /// Returns false (and stays in the current phase) if it isn't.
+    pub fn transition(&mut self, transition: Transition) -> bool {
+        match self.current_phase.next(transition.clone()) {
+            Some(phase) => {
+                self.current_phase = phase;
+                true
+            }
+            None => {
+                debug!(phase = ?self.current_phase, ?transition, "Phase transition not allowed");
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CombatAction::*;
+    use super::CombatPhase::*;
+    use super::*;
+
+    fn actions() -> Vec<(String, CombatAction)> {
+        vec![
+            ("Light Attack".to_owned(), LightAttack(None)),
+            ("Defend".to_owned(), Defend),
+        ]
+    }
+
+    /// Follow the transitions from the phase, failing the test if any of them isn't allowed.
+    fn follow(phase: CombatPhase, transitions: Vec<Transition>) -> CombatPhase {
+        transitions.into_iter().fold(phase, |phase, transition| {
+            let text = format!("{:?} from {:?}", transition, phase);
+            phase
+                .next(transition)
+                .unwrap_or_else(|| panic!("{} isn't allowed", text))
+        })
+    }
+
+    #[test]
+    fn targeted_actions_go_through_select_target() {
+        let phase = follow(
+            Drafting,
+            vec![
+                Transition::FinishDrafting,
+                Transition::Rolled,
+                Transition::ListActions(actions()),
+                Transition::ChooseAction(0),
+            ],
+        );
+        assert!(matches!(phase, SelectTarget(LightAttack(None))));
+        let phase = follow(phase, vec![Transition::ChooseTarget(2)]);
+        assert!(matches!(phase, Action(LightAttack(Some(2)))));
+        assert!(matches!(phase.next(Transition::EndTurn), Some(Drafting)));
+    }
+
+    #[test]
+    fn untargeted_actions_skip_select_target() {
+        let phase = follow(SelectAction(actions()), vec![Transition::ChooseAction(1)]);
+        assert!(matches!(phase, Action(Defend)));
+        assert!(matches!(phase.next(Transition::EndTurn), Some(Drafting)));
+    }
+
+    #[test]
+    fn cancelling_goes_back_to_listing_the_actions() {
+        let phase = follow(
+            SelectAction(actions()),
+            vec![Transition::ChooseAction(0), Transition::CancelAction],
+        );
+        assert!(matches!(&phase, SelectAction(actions) if actions.is_empty()));
+        // the actions can be listed again for the player to choose from
+        let phase = follow(phase, vec![Transition::ListActions(actions())]);
+        assert!(matches!(&phase, SelectAction(actions) if actions.len() == 2));
+    }
+
+    #[test]
+    fn illegal_transitions_are_refused() {
+        let illegal = [
+            (Drafting, Transition::ChooseAction(0)),
+            (Drafting, Transition::Rolled),
+            (Roll, Transition::FinishDrafting),
+            (SelectAction(vec![]), Transition::ChooseAction(0)),
+            (SelectAction(vec![]), Transition::ListActions(vec![])),
+            (SelectAction(actions()), Transition::ListActions(actions())),
+            (SelectAction(actions()), Transition::ChooseAction(2)),
+            (SelectAction(actions()), Transition::ChooseTarget(1)),
+            (SelectAction(actions()), Transition::CancelAction),
+            (SelectTarget(LightAttack(None)), Transition::EndTurn),
+            (Action(LightAttack(None)), Transition::EndTurn),
+            (Action(HeavyAttack(None)), Transition::EndTurn),
+            (Action(LightAttack(Some(1))), Transition::ChooseTarget(2)),
+            (Action(Defend), Transition::CancelAction),
+            (Reward(0, vec![]), Transition::WinBattle(0, vec![])),
+            (EncounterOver, Transition::WinBattle(0, vec![])),
+            (EncounterOver, Transition::EndEncounter),
+            (EncounterOver, Transition::FinishDrafting),
+        ];
+        for (phase, transition) in illegal {
+            let text = format!("{:?} from {:?}", transition, phase);
+            assert!(phase.next(transition).is_none(), "{} is allowed", text);
+        }
+    }
+
+    #[test]
+    fn the_battle_can_end_from_any_phase_of_a_turn() {
+        for phase in [
+            Drafting,
+            Roll,
+            SelectAction(actions()),
+            SelectTarget(HeavyAttack(None)),
+            Action(Defend),
+        ] {
+            assert!(matches!(
+                phase.next(Transition::WinBattle(1, vec![])),
+                Some(Reward(1, _))
+            ));
+            assert!(matches!(
+                phase.next(Transition::EndEncounter),
+                Some(EncounterOver)
+            ));
+        }
+        assert!(matches!(
+            Reward(0, vec![]).next(Transition::EndEncounter),
+            Some(EncounterOver)
+        ));
+    }
+
+    #[test]
+    fn refused_transitions_leave_the_phase_alone() {
+        let mut combat_state = CombatState::new(vec![]);
+        assert!(!combat_state.transition(Transition::EndTurn));
+        assert!(matches!(combat_state.current_phase, Drafting));
+        assert!(combat_state.transition(Transition::FinishDrafting));
+        assert!(matches!(combat_state.current_phase, Roll));
+    }
 }
@@ -1,61 +1,105 @@
 use crate::components::*;
-use crate::shared::Die;
+use crate::shared::{Color, Die, Face};
+use serde::{Deserialize, Serialize};
 use specs::{Builder, Entity, World, WorldExt};
 
-pub fn create_combatants(world: &mut World) -> Vec<Entity> {
-    let player = world
-        .create_entity()
-        .with(Named {
-            name: "Player".to_owned(),
-        })
-        .with(Health { hp: 100 })
-        .with(LightAttacker)
-        .with(HeavyAttacker {
-            ..Default::default()
-        })
-        .with(Defender {
-            ..Default::default()
-        })
-        .with(DicePool {
-            available: vec![Die::blue(6), Die::red(6), Die::yellow(6), Die::red(6)],
-            max_draft_amount: 2,
-            ..Default::default()
-        })
-        .build();
+/// Data file definition of a combatant, used for both party members and enemies.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CombatantDef {
+    pub name: String,
+    pub hp: usize,
+    #[serde(default)]
+    pub light_attacker: bool,
+    #[serde(default)]
+    pub heavy_attacker: bool,
+    #[serde(default)]
+    pub defender: bool,
+    pub max_draft_amount: usize,
+    pub dice: Vec<DieDef>,
+}
 
-    let red_goblin = world
+/// Data file definition of a die. Either `sides` for a standard die, or a list of `faces`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DieDef {
+    pub color: Color,
+    #[serde(default)]
+    pub sides: Option<usize>,
+    #[serde(default)]
+    pub faces: Option<Vec<Face>>,
+    #[serde(default)]
+    pub exploding: bool,
+    #[serde(default)]
+    pub critical: bool,
+    #[serde(default)]
+    pub fragile: bool,
+}
+
+impl CombatantDef {
+    /// Check every die of the combatant can be built, so `create_combatant` can't fail.
+    pub fn validate(&self) -> Result<(), String> {
+        for die in self.dice.iter() {
+            die.to_die()
+                .map_err(|err| format!("{} has an invalid die: {}", self.name, err))?;
+        }
+        Ok(())
+    }
+}
+
+impl DieDef {
+    pub fn to_die(&self) -> Result<Die, String> {
+        let mut die = match (&self.faces, self.sides) {
+            // an empty list of faces is how hidden dice are sent to clients, and can't be rolled anyway
+            (Some(faces), _) if faces.is_empty() => return Err("faces can't be empty".to_owned()),
+            (Some(faces), _) => Die::custom(self.color, faces.clone()),
+            (None, Some(0)) => return Err("sides has to be at least 1".to_owned()),
+            (None, Some(sides)) => Die::standard(self.color, sides),
+            (None, None) => return Err("die definition needs either sides or faces".to_owned()),
+        };
+        die.rules.exploding = self.exploding;
+        die.rules.critical = self.critical;
+        die.rules.fragile = self.fragile;
+        Ok(die)
+    }
+}
+
+/// Create a combatant entity from a definition that passed `CombatantDef::validate`.
+pub fn create_combatant(world: &mut World, def: &CombatantDef, enemy: bool) -> Entity {
+    let mut builder = world
         .create_entity()
         .with(Named {
-            name: "Red Goblin".to_owned(),
+            name: def.name.clone(),
         })
-        .with(Health { hp: 50 })
-        .with(LightAttacker)
-        .with(Defender {
-            ..Default::default()
+        .with(Health {
+            hp: def.hp,
+            max_hp: def.hp,
         })
         .with(DicePool {
-            available: vec![Die::red(4), Die::red(4)],
-            max_draft_amount: 2,
+            available: def
+                .dice
+                .iter()
+                .map(|die| {
+                    die.to_die()
+                        .expect("combatant definitions are validated on load")
+                })
+                .collect(),
+            max_draft_amount: def.max_draft_amount,
             ..Default::default()
-        })
-        .build();
-
-    let blue_goblin = world
-        .create_entity()
-        .with(Named {
-            name: "Blue Goblin".to_owned(),
-        })
-        .with(Health { hp: 50 })
-        .with(LightAttacker)
-        .with(Defender {
+        });
+    if def.light_attacker {
+        builder = builder.with(LightAttacker);
+    }
+    if def.heavy_attacker {
+        builder = builder.with(HeavyAttacker {
             ..Default::default()
-        })
-        .with(DicePool {
-            available: vec![Die::blue(4), Die::blue(4)],
-            max_draft_amount: 2,
+        });
+    }
+    if def.defender {
+        builder = builder.with(Defender {
             ..Default::default()
-        })
-        .build();
-
-    vec![player, red_goblin, blue_goblin]
+        });
+    }
+    if enemy {
+        builder = builder.with(Enemy);
+    }
+    builder.build()
 }
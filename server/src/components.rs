@@ -1,20 +1,23 @@
 use crate::shared::Die;
+use serde::{Deserialize, Serialize};
 use specs::{Component, DenseVecStorage};
 
+// Marks the enemies in a campaign run, or the second seat's team in a PvP match
 #[derive(Component)]
 pub struct Enemy;
 
-#[derive(Component)]
+#[derive(Component, Clone, Serialize, Deserialize)]
 pub struct Named {
     pub name: String,
 }
 
-#[derive(Component)]
+#[derive(Component, Clone, Serialize, Deserialize)]
 pub struct Health {
     pub hp: usize,
+    pub max_hp: usize,
 }
 
-#[derive(Component, Default)]
+#[derive(Component, Clone, Default, Serialize, Deserialize)]
 pub struct DicePool {
     pub available: Vec<Die>,
     pub max_draft_amount: usize,
@@ -22,15 +25,40 @@ pub struct DicePool {
     pub rolled: Vec<Die>,
 }
 
+impl DicePool {
+    /// Return dice that were used during a turn to the available dice. Fragile dice are used up
+    /// instead, and handed back so the caller can log them.
+    pub fn return_used(&mut self, dice: Vec<Die>) -> Vec<Die> {
+        let mut broken = vec![];
+        for mut die in dice {
+            die.reset();
+            if die.rules.fragile {
+                broken.push(die);
+            } else {
+                self.available.push(die);
+            }
+        }
+        broken
+    }
+
+    /// Return dice that were never used (e.g. drafted or prepped when a battle ended) to the available dice.
+    pub fn return_unused(&mut self, dice: Vec<Die>) {
+        for mut die in dice {
+            die.reset();
+            self.available.push(die);
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct LightAttacker;
 
-#[derive(Component, Default)]
+#[derive(Component, Clone, Default, Serialize, Deserialize)]
 pub struct HeavyAttacker {
     pub prepped_attack: Vec<Die>,
 }
 
-#[derive(Component, Default)]
+#[derive(Component, Clone, Default, Serialize, Deserialize)]
 pub struct Defender {
     pub prepped_defense: Vec<Die>,
 }
@@ -0,0 +1,137 @@
+use crate::accounts::SECRET_FILE;
+use crate::campaign::CAMPAIGN_FILE;
+use crate::players::PLAYERS_FILE;
+use crate::pvp::PVP_ENCOUNTER_FILE;
+use crate::room::{RoomSettings, DEFAULT_TURN_TIMER};
+use crate::save::SAVE_DIR;
+use serde::Deserialize;
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tracing::info;
+
+pub const CONFIG_FILE: &str = "config.toml";
+// default directory for data that isn't part of any room, relative to the working directory
+pub const DATA_DIR: &str = "data";
+
+/// Server settings, from `CONFIG_FILE` (or the file named by `DICE_COMBAT_CONFIG`) if there is one.
+/// Every setting can be overridden by an environment variable named after it, e.g. `DICE_COMBAT_MAX_ROOMS`,
+/// and `PORT` overrides the port, since that's how Cloud Run tells the server where to listen.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub listen_address: String,
+    pub port: u16,
+    pub max_rooms: usize,
+    pub max_log_entries: usize, // oldest combat log entries are dropped past this, 0 keeps them all
+    pub rng_seed: Option<u64>, // every new room uses this seed instead of the current time, e.g. to reproduce a bug
+    pub campaign_file: String,
+    pub pvp_encounter_file: String,
+    pub turn_timer: u64, // default seconds for each phase of a turn, 0 for no timer
+    pub save_dir: String, // rooms are saved here, so should be on persistent storage
+    pub data_dir: String, // guest players and the token secret are kept here, also on persistent storage
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            listen_address: "0.0.0.0".to_owned(),
+            port: 9000,
+            max_rooms: 1000,
+            max_log_entries: 500,
+            rng_seed: None,
+            campaign_file: CAMPAIGN_FILE.to_owned(),
+            pvp_encounter_file: PVP_ENCOUNTER_FILE.to_owned(),
+            turn_timer: DEFAULT_TURN_TIMER,
+            save_dir: SAVE_DIR.to_owned(),
+            data_dir: DATA_DIR.to_owned(),
+        }
+    }
+}
+
+impl Config {
+    /// Read the config file and environment variables. Fails if the file can't be parsed, or a variable
+    /// has an invalid value, rather than quietly running with the default.
+    pub fn load() -> Result<Self, String> {
+        let path = env::var("DICE_COMBAT_CONFIG").unwrap_or_else(|_| CONFIG_FILE.to_owned());
+        let mut config: Config = match fs::read_to_string(&path) {
+            Ok(data) => toml::from_str(&data)
+                .map_err(|err| format!("Could not parse config file {}: {}", path, err))?,
+            Err(_) => Config::default(),
+        };
+        override_from_env("DICE_COMBAT_LISTEN_ADDRESS", &mut config.listen_address)?;
+        override_from_env("DICE_COMBAT_PORT", &mut config.port)?;
+        override_from_env("PORT", &mut config.port)?;
+        override_from_env("DICE_COMBAT_MAX_ROOMS", &mut config.max_rooms)?;
+        override_from_env("DICE_COMBAT_MAX_LOG_ENTRIES", &mut config.max_log_entries)?;
+        override_option_from_env("DICE_COMBAT_RNG_SEED", &mut config.rng_seed)?;
+        override_from_env("DICE_COMBAT_CAMPAIGN_FILE", &mut config.campaign_file)?;
+        override_from_env(
+            "DICE_COMBAT_PVP_ENCOUNTER_FILE",
+            &mut config.pvp_encounter_file,
+        )?;
+        override_from_env("DICE_COMBAT_TURN_TIMER", &mut config.turn_timer)?;
+        override_from_env("DICE_COMBAT_SAVE_DIR", &mut config.save_dir)?;
+        override_from_env("DICE_COMBAT_DATA_DIR", &mut config.data_dir)?;
+        info!(?config, "Loaded config");
+        Ok(config)
+    }
+
+    pub fn address(&self) -> String {
+        format!("{}:{}", self.listen_address, self.port)
+    }
+
+    /// Profiles and match history of every guest player.
+    pub fn players_file(&self) -> PathBuf {
+        Path::new(&self.data_dir).join(PLAYERS_FILE)
+    }
+
+    /// Secret that guest player tokens are signed with, unless it's set with `TOKEN_SECRET`.
+    pub fn token_secret_file(&self) -> PathBuf {
+        Path::new(&self.data_dir).join(SECRET_FILE)
+    }
+
+    /// Settings for a new room, before any the client asks for.
+    pub fn room_settings(&self) -> RoomSettings {
+        RoomSettings {
+            turn_timer: Some(self.turn_timer).filter(|seconds| *seconds > 0),
+            ..Default::default()
+        }
+    }
+}
+
+fn override_from_env<T>(name: &str, value: &mut T) -> Result<(), String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Ok(var) = env::var(name) {
+        *value = parse_env(name, &var)?;
+    }
+    Ok(())
+}
+
+/// Like `override_from_env`, but an empty variable unsets the value.
+fn override_option_from_env<T>(name: &str, value: &mut Option<T>) -> Result<(), String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match env::var(name) {
+        Ok(var) if var.is_empty() => *value = None,
+        Ok(var) => *value = Some(parse_env(name, &var)?),
+        Err(_) => {}
+    }
+    Ok(())
+}
+
+fn parse_env<T>(name: &str, var: &str) -> Result<T, String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    var.parse()
+        .map_err(|err| format!("Invalid value for {}: {}", name, err))
+}
@@ -1 +0,0 @@
-pub const FONT_SIZE: u32 = 24;
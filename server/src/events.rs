@@ -1,14 +1,63 @@
+use specs::Entity;
+
+// Each kind of event has its own channel, a specs `EventChannel<T>` resource. Systems register a reader
+// for each kind they care about in `System::setup`, so anything can react to an event (AI, logging,
+// achievements...) without the system sending it knowing about it. Every reader sees every event once.
+
+// Events sent by the room, from client messages:
+
+// Draft a die by its position in available_dice of the DicePool
+#[derive(Debug, Copy, Clone)]
+pub struct DraftDie(pub usize);
+
+// Done drafting, so the drafted dice get rolled
+#[derive(Debug, Copy, Clone)]
+pub struct FinishDrafting;
+
+// Choose a reward by its position in the offered rewards, applied to a die by its position in available_dice
+#[derive(Debug, Copy, Clone)]
+pub struct ChooseReward(pub usize, pub Option<usize>);
+
+// Choose an action by its position in the possible actions of the SelectAction phase
 #[derive(Debug, Copy, Clone)]
-pub enum Event {
-    // Draft a die by its position in available_dice of the DicePool
-    DraftDie(usize),
+pub struct ChooseAction(pub usize);
+
+// Choose the target of the chosen action by its position in CombatState::combatants
+#[derive(Debug, Copy, Clone)]
+pub struct ChooseTarget(pub usize);
+
+// Go back to choosing an action, before a target has been chosen
+#[derive(Debug, Copy, Clone)]
+pub struct CancelAction;
+
+// Events sent by systems, about what happened in the battle:
+
+// An attack landed, with the damage that got through after `blocked` was taken off
+#[derive(Debug, Copy, Clone)]
+pub struct DamageDealt {
+    pub attacker: Entity,
+    pub target: Entity,
+    pub heavy: bool,
+    pub damage: usize,
+    pub blocked: usize,
 }
 
-// global event queue
-// RW: in future, it might make sense to separate this out into separate queues based on different event types?
-#[derive(Default)]
-pub struct EventQueue {
-    pub events: Vec<Event>, // possibly read many times by different systems
-    pub new_events: Vec<Event>, // possibly written many times by different systems
-                            // at end of each frame, `events` is cleared and `new_events` replaces it
+// A combatant's health went down to 0
+#[derive(Debug, Copy, Clone)]
+pub struct CombatantDied {
+    pub combatant: Entity,
+}
+
+// A combatant's turn started, including the first turn of each battle
+#[derive(Debug, Copy, Clone)]
+pub struct TurnStarted {
+    pub round: usize,
+    pub combatant: Entity,
+}
+
+// A combatant's action was done, just before the next turn starts
+#[derive(Debug, Copy, Clone)]
+pub struct TurnEnded {
+    pub round: usize,
+    pub combatant: Entity,
 }
@@ -0,0 +1,29 @@
+// The server is a library with a thin binary on top (main.rs), so the integration tests in `tests/`
+// can set up rooms and play them without going through a socket.
+pub mod accounts;
+pub mod admin;
+pub mod ai;
+pub mod campaign;
+pub mod combat_state;
+pub mod combatant;
+pub mod components;
+pub mod config;
+pub mod events;
+pub mod log;
+pub mod logging;
+pub mod matchmaking;
+pub mod metrics;
+pub mod players;
+// probability.rs is copied between server and client too
+#[allow(dead_code)]
+pub mod probability;
+pub mod pvp;
+pub mod redaction;
+pub mod rng;
+pub mod room;
+pub mod save;
+// shared.rs is copied between server and client, so not everything in it is used on both sides
+#[allow(dead_code)]
+pub mod shared;
+pub mod systems;
+pub mod websocket;
@@ -1,15 +1,30 @@
-#[derive(Default)]
+use crate::shared::LogEntry;
+use serde::{Deserialize, Serialize};
+
+/// History of the combat log, which is sent to clients as is.
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct CombatLog {
-    pub logs: Vec<String>,
+    pub entries: Vec<LogEntry>,
+    #[serde(default)]
+    pub dropped: usize, // oldest entries that were dropped to keep the history short
 }
 
-const MAX_LOGS: usize = 10;
-
 impl CombatLog {
-    pub fn add(&mut self, s: String) {
-        self.logs.push(s);
-        if self.logs.len() > MAX_LOGS {
-            self.logs.remove(0);
+    pub fn add(&mut self, entry: LogEntry) {
+        self.entries.push(entry);
+    }
+
+    /// Drop the oldest entries, keeping at most `max_entries`.
+    pub fn trim(&mut self, max_entries: usize) {
+        if self.entries.len() > max_entries {
+            let excess = self.entries.len() - max_entries;
+            self.entries.drain(..excess);
+            self.dropped += excess;
         }
     }
+
+    /// Every entry ever added, including dropped ones.
+    pub fn total_added(&self) -> usize {
+        self.dropped + self.entries.len()
+    }
 }
@@ -0,0 +1,98 @@
+use serde_json::{Map, Value};
+use std::env;
+use std::fmt;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::fmt::format::{JsonFields, Writer};
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::EnvFilter;
+
+const DEFAULT_FILTER: &str = "info";
+
+/// Set up logging. Levels come from `RUST_LOG` (e.g. `RUST_LOG=debug` or `RUST_LOG=info,dice_combat::room=trace`),
+/// and `LOG_FORMAT=json` switches from human readable lines to one JSON object per line, for Cloud Logging.
+pub fn init() {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    if env::var("LOG_FORMAT").is_ok_and(|format| format == "json") {
+        builder
+            .fmt_fields(JsonFields::new())
+            .event_format(CloudLogging)
+            .init();
+    } else {
+        builder.init();
+    }
+}
+
+/// Formats events as the structured JSON Cloud Logging expects: `severity` and `message`,
+/// alongside the event's other fields and those of the spans it happened in (e.g. `room` and `client`).
+struct CloudLogging;
+
+impl<S, N> FormatEvent<S, N> for CloudLogging
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut entry = Map::new();
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                let extensions = span.extensions();
+                // span fields are stored already formatted, as a JSON object thanks to JsonFields
+                if let Some(fields) = extensions.get::<FormattedFields<N>>() {
+                    if let Ok(Value::Object(fields)) = serde_json::from_str(fields) {
+                        entry.extend(fields);
+                    }
+                }
+            }
+        }
+        event.record(&mut FieldVisitor(&mut entry));
+        let metadata = event.metadata();
+        let severity = match *metadata.level() {
+            Level::ERROR => "ERROR",
+            Level::WARN => "WARNING",
+            Level::INFO => "INFO",
+            Level::DEBUG | Level::TRACE => "DEBUG",
+        };
+        // events from crates using `log` (like ws) come through with their real target in a field
+        let target = entry
+            .remove("log.target")
+            .unwrap_or_else(|| metadata.target().into());
+        entry.retain(|name, _| !name.starts_with("log."));
+        entry.insert("severity".to_owned(), severity.into());
+        entry.insert("target".to_owned(), target);
+        writeln!(writer, "{}", Value::Object(entry))
+    }
+}
+
+struct FieldVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_owned(), format!("{:?}", value).into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_owned(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_owned(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_owned(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_owned(), value.into());
+    }
+}
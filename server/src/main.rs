@@ -1,31 +1,17 @@
-use crate::events::EventQueue;
-use quad_rand as qrand;
-use std::sync::Arc;
-use std::sync::Mutex;
-use std::time::SystemTime;
-use ws::listen;
-
-mod combat_state;
-mod combatant;
-mod components;
-mod constants;
-mod events;
-mod log;
-mod shared;
-mod systems;
-mod websocket;
+use dice_combat::config::Config;
+use dice_combat::logging;
+use dice_combat::metrics;
+use dice_combat::websocket;
+use dice_combat::websocket::Server;
+use std::process;
+use tracing::error;
 
 fn main() {
-    // seed random to current timestamp
-    let time = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap();
-    qrand::srand(time.as_secs());
-
-    let server = Arc::new(Mutex::new(websocket::Server::new()));
-    listen("0.0.0.0:9000", |out| websocket::Connection {
-        server: Arc::clone(&server),
-        out,
-    })
-    .unwrap()
+    logging::init();
+    metrics::install_panic_hook();
+    let server = Config::load().and_then(Server::new).unwrap_or_else(|err| {
+        error!(%err, "Could not start the server");
+        process::exit(1);
+    });
+    websocket::bind(server).unwrap().run().unwrap();
 }
@@ -0,0 +1,78 @@
+use std::time::Duration;
+use std::time::Instant;
+use ws::util::Token;
+use ws::Sender;
+
+// ws timeout token for checking whether a connection has been waiting for a match for too long
+pub const MATCHMAKING: Token = Token(2);
+pub const MATCHMAKING_TIMEOUT: u64 = 120;
+// players with ratings are only paired with opponents within this many points of them
+const MAX_RATING_GAP: u32 = 200;
+
+pub struct QueuedPlayer {
+    pub sender: Sender,
+    pub player: Option<String>, // id of the guest player, who gets their seat in the match kept for them
+    rating: Option<u32>,
+    joined: Instant,
+}
+
+/// Connections waiting to be paired up for a PvP match, in the order they started looking.
+#[derive(Default)]
+pub struct MatchQueue {
+    waiting: Vec<QueuedPlayer>,
+}
+
+impl MatchQueue {
+    /// Pair the connection with the closest rated opponent already waiting, or add it to the queue
+    /// if there isn't one. Returns the opponent, who is taken out of the queue.
+    pub fn find_match(
+        &mut self,
+        sender: Sender,
+        player: Option<String>,
+        rating: Option<u32>,
+    ) -> Option<QueuedPlayer> {
+        self.cancel(sender.token());
+        let gap = |other: Option<u32>| match (rating, other) {
+            (Some(rating), Some(other)) => rating.abs_diff(other),
+            // unrated players can play anyone
+            _ => 0,
+        };
+        let opponent = self
+            .waiting
+            .iter()
+            .enumerate()
+            .filter(|(_, player)| gap(player.rating) <= MAX_RATING_GAP)
+            .min_by_key(|(_, player)| gap(player.rating))
+            .map(|(n, _)| n);
+        match opponent {
+            Some(n) => Some(self.waiting.remove(n)),
+            None => {
+                self.waiting.push(QueuedPlayer {
+                    sender,
+                    player,
+                    rating,
+                    joined: Instant::now(),
+                });
+                None
+            }
+        }
+    }
+
+    /// Take the connection out of the queue. Returns false if it wasn't waiting.
+    pub fn cancel(&mut self, token: Token) -> bool {
+        let len = self.waiting.len();
+        self.waiting.retain(|player| player.sender.token() != token);
+        self.waiting.len() != len
+    }
+
+    /// Take the connection out of the queue if it's been waiting longer than the timeout.
+    /// Returns false if it wasn't waiting, or still has time left.
+    pub fn time_out(&mut self, token: Token) -> bool {
+        let timeout = Duration::from_secs(MATCHMAKING_TIMEOUT);
+        let expired = self
+            .waiting
+            .iter()
+            .any(|player| player.sender.token() == token && player.joined.elapsed() >= timeout);
+        expired && self.cancel(token)
+    }
+}
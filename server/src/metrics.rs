@@ -0,0 +1,68 @@
+use crate::websocket::Server;
+use std::backtrace::Backtrace;
+use std::fmt::Write;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use tracing::error;
+
+// Counters that only ever go up, since the server started. Prometheus works out rates from them
+// (e.g. messages per second), so there's no need to track time windows here.
+pub static MESSAGES: AtomicU64 = AtomicU64::new(0);
+pub static SYSTEM_RUNS: AtomicU64 = AtomicU64::new(0);
+pub static PANICS: AtomicU64 = AtomicU64::new(0);
+
+pub fn count(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Count every panic, and log it like everything else instead of printing the usual panic message.
+pub fn install_panic_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        count(&PANICS);
+        // only captured if RUST_BACKTRACE is set, like the usual panic message
+        let backtrace = Backtrace::capture();
+        error!(panic = %info, %backtrace, "Panicked");
+    }));
+}
+
+/// Current metrics in the Prometheus text format.
+pub fn render(server: &Server) -> String {
+    let connections: usize = server.rooms.values().map(|room| room.clients.len()).sum();
+    let mut text = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: u64| {
+        writeln!(text, "# HELP {} {}", name, help).unwrap();
+        writeln!(text, "# TYPE {} {}", name, kind).unwrap();
+        writeln!(text, "{} {}", name, value).unwrap();
+    };
+    metric(
+        "dice_combat_connections",
+        "gauge",
+        "Open websocket connections.",
+        connections as u64,
+    );
+    metric(
+        "dice_combat_rooms",
+        "gauge",
+        "Rooms with a game world loaded.",
+        server.rooms.len() as u64,
+    );
+    metric(
+        "dice_combat_messages_total",
+        "counter",
+        "Websocket messages received from clients.",
+        MESSAGES.load(Ordering::Relaxed),
+    );
+    metric(
+        "dice_combat_system_runs_total",
+        "counter",
+        "Runs of the game systems, once per message or timed out turn, across all rooms.",
+        SYSTEM_RUNS.load(Ordering::Relaxed),
+    );
+    metric(
+        "dice_combat_panics_total",
+        "counter",
+        "Panics, including ones caught while handling a message.",
+        PANICS.load(Ordering::Relaxed),
+    );
+    text
+}
@@ -0,0 +1,292 @@
+use crate::shared::{Die, LogEntry, PlayerProfile};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+// in the data directory, see `Config::players_file`
+pub const PLAYERS_FILE: &str = "players.json";
+pub const STARTING_RATING: u32 = 1500;
+// how far a single match can move an Elo rating
+const RATING_K: f64 = 32.0;
+const LEADERBOARD_SIZE: usize = 100;
+const MAX_NAME_LENGTH: usize = 24;
+
+/// Every guest player's profile, and the results of every recorded PvP match, stored in a single JSON file.
+#[derive(Default, Serialize, Deserialize)]
+pub struct PlayerStore {
+    pub players: BTreeMap<String, PlayerRecord>,
+    pub matches: Vec<MatchRecord>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PlayerRecord {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub unlocked_dice: Vec<Die>,
+    pub rating: u32,
+    pub wins: usize,
+    pub losses: usize,
+    pub matches: Vec<usize>, // positions in PlayerStore::matches, oldest first
+}
+
+impl PlayerRecord {
+    fn new(player: &str) -> Self {
+        PlayerRecord {
+            // e.g. "Guest 3f2a", until they pick a name
+            name: format!("Guest {}", &player[player.len().saturating_sub(4)..]),
+            unlocked_dice: vec![],
+            rating: STARTING_RATING,
+            wins: 0,
+            losses: 0,
+            matches: vec![],
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MatchRecord {
+    pub room: String,
+    pub encounter: String,
+    pub players: Vec<String>, // by seat
+    pub winner: usize,
+    pub rating_changes: Vec<i32>, // by seat
+    pub finished_at: u64,         // seconds since the unix epoch
+    pub replay: Vec<LogEntry>,    // the whole combat log of the match
+}
+
+/// A PvP match that just ended in a room, waiting to be recorded.
+pub struct FinishedMatch {
+    pub room: String,
+    pub encounter: String,
+    pub players: Vec<Option<String>>, // by seat, if the player in it identified themselves
+    pub winner: usize,
+    pub replay: Vec<LogEntry>,
+}
+
+#[derive(Serialize)]
+pub struct LeaderboardEntry {
+    pub player: String,
+    pub name: String,
+    pub rating: u32,
+    pub wins: usize,
+    pub losses: usize,
+}
+
+impl PlayerStore {
+    /// Load the store, starting a new one if the file doesn't exist yet. Fails if the file can't be
+    /// read or parsed, rather than starting over and losing every player.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(data) => serde_json::from_str(&data)
+                .map_err(|err| format!("Could not parse players file {}: {}", path.display(), err)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(PlayerStore::default()),
+            Err(err) => Err(format!(
+                "Could not read players file {}: {}",
+                path.display(),
+                err
+            )),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let data = serde_json::to_string(self)?;
+        // write to a temporary file first, so a crash mid-write can't corrupt the previous file
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, data)?;
+        fs::rename(tmp_path, path)
+    }
+
+    /// Profile of the player. New players only get stored once they change something, e.g. their name or
+    /// rating, so connecting without a token over and over doesn't fill up the store.
+    pub fn profile(&self, player: &str) -> PlayerProfile {
+        let record = self
+            .players
+            .get(player)
+            .cloned()
+            .unwrap_or_else(|| PlayerRecord::new(player));
+        PlayerProfile {
+            id: player.to_owned(),
+            name: record.name,
+            unlocked_dice: record.unlocked_dice,
+            rating: record.rating,
+            wins: record.wins,
+            losses: record.losses,
+        }
+    }
+
+    /// Add dice the player won to their profile.
+    pub fn unlock_dice(&mut self, player: &str, dice: Vec<Die>) {
+        self.players
+            .entry(player.to_owned())
+            .or_insert_with(|| PlayerRecord::new(player))
+            .unlocked_dice
+            .extend(dice);
+    }
+
+    pub fn set_name(&mut self, player: &str, name: &str) -> Result<(), String> {
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+            return Err(format!("Names need 1 to {} characters", MAX_NAME_LENGTH));
+        }
+        if name.chars().any(char::is_control) {
+            return Err("Names can't contain control characters".to_owned());
+        }
+        self.players
+            .entry(player.to_owned())
+            .or_insert_with(|| PlayerRecord::new(player))
+            .name = name.to_owned();
+        Ok(())
+    }
+
+    pub fn rating(&self, player: &str) -> u32 {
+        self.players
+            .get(player)
+            .map_or(STARTING_RATING, |record| record.rating)
+    }
+
+    /// Record the result of a match and update both players' ratings. Matches where either
+    /// player didn't identify themselves aren't recorded, since there's nobody to rate.
+    pub fn record(&mut self, finished: FinishedMatch) -> Option<&MatchRecord> {
+        let winner = finished.winner;
+        let players: Vec<String> = finished.players.into_iter().collect::<Option<_>>()?;
+        if players.len() != 2 || players[0] == players[1] {
+            return None;
+        }
+        let ratings: Vec<u32> = players.iter().map(|player| self.rating(player)).collect();
+        let change = rating_change(ratings[winner], ratings[1 - winner]);
+        let rating_changes: Vec<i32> = (0..players.len())
+            .map(|seat| if seat == winner { change } else { -change })
+            .collect();
+
+        let n = self.matches.len();
+        for (seat, player) in players.iter().enumerate() {
+            let record = self
+                .players
+                .entry(player.clone())
+                .or_insert_with(|| PlayerRecord::new(player));
+            record.rating = record.rating.saturating_add_signed(rating_changes[seat]);
+            if seat == winner {
+                record.wins += 1;
+            } else {
+                record.losses += 1;
+            }
+            record.matches.push(n);
+        }
+        self.matches.push(MatchRecord {
+            room: finished.room,
+            encounter: finished.encounter,
+            players,
+            winner,
+            rating_changes,
+            finished_at: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            replay: finished.replay,
+        });
+        self.matches.last()
+    }
+
+    /// Players with the highest ratings, best first.
+    pub fn leaderboard(&self) -> Vec<LeaderboardEntry> {
+        let mut entries: Vec<LeaderboardEntry> = self
+            .players
+            .iter()
+            .map(|(player, record)| LeaderboardEntry {
+                player: player.clone(),
+                name: record.name.clone(),
+                rating: record.rating,
+                wins: record.wins,
+                losses: record.losses,
+            })
+            .collect();
+        entries.sort_by(|a, b| b.rating.cmp(&a.rating).then(a.player.cmp(&b.player)));
+        entries.truncate(LEADERBOARD_SIZE);
+        entries
+    }
+}
+
+/// Elo rating points the winner takes from the loser.
+fn rating_change(winner: u32, loser: u32) -> i32 {
+    let expected = 1.0 / (1.0 + 10f64.powf((f64::from(loser) - f64::from(winner)) / 400.0));
+    (RATING_K * (1.0 - expected)).round() as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finished(players: [&str; 2], winner: usize) -> FinishedMatch {
+        FinishedMatch {
+            room: "room".to_owned(),
+            encounter: "Arena".to_owned(),
+            players: players
+                .iter()
+                .map(|player| Some(player.to_string()))
+                .collect(),
+            winner,
+            replay: vec![],
+        }
+    }
+
+    #[test]
+    fn evenly_rated_players_trade_half_the_k_factor() {
+        assert_eq!(rating_change(1500, 1500), 16);
+        // beating a much better player is worth more than beating a much worse one
+        assert!(rating_change(1300, 1700) > 16);
+        assert!(rating_change(1700, 1300) < 16);
+        assert_eq!(
+            rating_change(1300, 1700) + rating_change(1700, 1300),
+            RATING_K as i32
+        );
+    }
+
+    #[test]
+    fn matches_update_the_winner_and_the_loser() {
+        let mut store = PlayerStore::default();
+        let record = store.record(finished(["alice", "bob"], 1)).unwrap();
+        assert_eq!(record.rating_changes, [-16, 16]);
+
+        let alice = &store.players["alice"];
+        assert_eq!((alice.rating, alice.wins, alice.losses), (1484, 0, 1));
+        let bob = &store.players["bob"];
+        assert_eq!((bob.rating, bob.wins, bob.losses), (1516, 1, 0));
+        assert_eq!(alice.matches, [0]);
+        assert_eq!(bob.matches, [0]);
+        assert_eq!(store.leaderboard()[0].player, "bob");
+    }
+
+    #[test]
+    fn ratings_cant_go_below_zero() {
+        let mut store = PlayerStore::default();
+        for (player, rating) in [("winner", 0), ("loser", 10)] {
+            store.players.insert(
+                player.to_owned(),
+                PlayerRecord {
+                    rating,
+                    ..PlayerRecord::new(player)
+                },
+            );
+        }
+        let record = store.record(finished(["winner", "loser"], 0)).unwrap();
+        assert!(record.rating_changes[0] > 10);
+        assert_eq!(store.rating("loser"), 0);
+    }
+
+    #[test]
+    fn matches_need_two_identified_players() {
+        let mut store = PlayerStore::default();
+        let mut anonymous = finished(["alice", "bob"], 0);
+        anonymous.players[1] = None;
+        assert!(store.record(anonymous).is_none());
+        assert!(store.record(finished(["alice", "alice"], 0)).is_none());
+        assert!(store.players.is_empty() && store.matches.is_empty());
+    }
+}
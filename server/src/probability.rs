@@ -0,0 +1,187 @@
+// TODO: eventually extract to another crate lib, but for now copy-paste between server and client (like shared.rs)
+
+use crate::shared::{Die, Face, MAX_EXPLOSIONS};
+use std::collections::BTreeMap;
+
+/// Exact probability distribution of the damage of an attack, following the same rules as `attack_damage`.
+#[derive(Clone, Debug)]
+pub struct DamageDistribution {
+    pub probabilities: BTreeMap<usize, f64>, // damage -> chance of dealing exactly that much
+}
+
+impl DamageDistribution {
+    /// Distribution of the damage dealt by attacking with all of the given dice. Dice that were
+    /// already rolled (e.g. prepped for a heavy attack) always count for what they rolled.
+    pub fn of_attack(dice: &[Die]) -> Self {
+        // Crit faces double the whole attack, so keep track of the sum and the number of crits separately
+        let mut outcomes: BTreeMap<(usize, u32), f64> = BTreeMap::new();
+        outcomes.insert((0, 0), 1.0);
+        for die in dice {
+            let mut next = BTreeMap::new();
+            for ((sum, crits), p) in outcomes.iter() {
+                for ((value, die_crits), die_p) in die_outcomes(die).iter() {
+                    *next.entry((sum + value, crits + die_crits)).or_insert(0.0) += p * die_p;
+                }
+            }
+            outcomes = next;
+        }
+
+        let mut probabilities = BTreeMap::new();
+        for ((sum, crits), p) in outcomes {
+            *probabilities.entry(sum * 2usize.pow(crits)).or_insert(0.0) += p;
+        }
+        DamageDistribution { probabilities }
+    }
+
+    pub fn expected(&self) -> f64 {
+        self.probabilities
+            .iter()
+            .map(|(damage, p)| *damage as f64 * p)
+            .sum()
+    }
+
+    /// Chance of dealing at least the given amount of damage.
+    pub fn chance_at_least(&self, damage: usize) -> f64 {
+        self.probabilities.range(damage..).map(|(_, p)| p).sum()
+    }
+
+    /// Chance of killing a target with the given HP, after their prepped defense blocks some of the damage.
+    pub fn chance_to_kill(&self, hp: usize, blocked: usize) -> f64 {
+        self.chance_at_least(hp + blocked)
+    }
+}
+
+/// Outcomes of a single die, as (damage, number of crit faces) -> probability.
+fn die_outcomes(die: &Die) -> BTreeMap<(usize, u32), f64> {
+    let mut outcomes = BTreeMap::new();
+    if die.rolled_value.is_some() {
+        let faces: Vec<Face> = die.rolled_faces().collect();
+        outcomes.insert(faces_outcome(die, &faces), 1.0);
+    } else {
+        roll_outcomes(die, vec![], 1.0, &mut outcomes);
+    }
+    outcomes
+}
+
+/// Add up every way the die can roll, rolling again for each explosion like RollingSystem does.
+fn roll_outcomes(die: &Die, rolled: Vec<Face>, p: f64, outcomes: &mut BTreeMap<(usize, u32), f64>) {
+    let face_p = p / die.sides() as f64;
+    for face in die.faces.iter() {
+        let mut faces = rolled.clone();
+        faces.push(*face);
+        let explodes =
+            die.rules.exploding && Some(*face) == die.max_face() && faces.len() <= MAX_EXPLOSIONS;
+        if explodes {
+            roll_outcomes(die, faces, face_p, outcomes);
+        } else {
+            *outcomes.entry(faces_outcome(die, &faces)).or_insert(0.0) += face_p;
+        }
+    }
+}
+
+fn faces_outcome(die: &Die, faces: &[Face]) -> (usize, u32) {
+    let damage = faces
+        .iter()
+        .map(|face| {
+            if die.is_critical_hit(*face) {
+                face.attack_value() * 2
+            } else {
+                face.attack_value()
+            }
+        })
+        .sum();
+    let crits = faces.iter().filter(|face| **face == Face::Crit).count() as u32;
+    (damage, crits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::Color;
+
+    /// Check the damage -> probability pairs, allowing for floating point error.
+    fn assert_distribution(distribution: &DamageDistribution, expected: &[(usize, f64)]) {
+        let damages: Vec<usize> = distribution.probabilities.keys().copied().collect();
+        let expected_damages: Vec<usize> = expected.iter().map(|(damage, _)| *damage).collect();
+        assert_eq!(damages, expected_damages);
+        for (damage, p) in expected {
+            let actual = distribution.probabilities[damage];
+            assert!(
+                (actual - p).abs() < 1e-9,
+                "P({}) is {}, not {}",
+                damage,
+                actual,
+                p
+            );
+        }
+    }
+
+    fn die(faces: &[Face]) -> Die {
+        Die::custom(Color::Red, faces.to_vec())
+    }
+
+    #[test]
+    fn custom_faces() {
+        let distribution = DamageDistribution::of_attack(&[die(&[
+            Face::Number(1),
+            Face::Number(1),
+            Face::Number(4),
+            Face::Shield(2),
+        ])]);
+        // shields do no damage
+        assert_distribution(&distribution, &[(0, 0.25), (1, 0.5), (4, 0.25)]);
+        assert!((distribution.expected() - 1.5).abs() < 1e-9);
+        assert!((distribution.chance_at_least(1) - 0.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn dice_are_added_together() {
+        let distribution = DamageDistribution::of_attack(&[Die::red(2), Die::red(2)]);
+        assert_distribution(&distribution, &[(2, 0.25), (3, 0.5), (4, 0.25)]);
+    }
+
+    #[test]
+    fn crit_faces_double_the_whole_attack() {
+        let distribution = DamageDistribution::of_attack(&[
+            die(&[Face::Number(2), Face::Crit]),
+            die(&[Face::Number(3), Face::Crit]),
+        ]);
+        // 2 + 3, a single crit doubling the other die, or two crits doubling nothing
+        assert_distribution(&distribution, &[(0, 0.25), (4, 0.25), (5, 0.25), (6, 0.25)]);
+    }
+
+    #[test]
+    fn critical_dice_double_their_max_face() {
+        let distribution = DamageDistribution::of_attack(&[Die::red(2).critical()]);
+        assert_distribution(&distribution, &[(1, 0.5), (4, 0.5)]);
+    }
+
+    #[test]
+    fn exploding_dice_roll_again_on_their_max_face() {
+        let distribution = DamageDistribution::of_attack(&[Die::red(2).exploding()]);
+        let total: f64 = distribution.probabilities.values().sum();
+        assert!((total - 1.0).abs() < 1e-9);
+        assert!((distribution.probabilities[&1] - 0.5).abs() < 1e-9);
+        assert!((distribution.probabilities[&3] - 0.25).abs() < 1e-9);
+        assert!((distribution.probabilities[&5] - 0.125).abs() < 1e-9);
+    }
+
+    #[test]
+    fn explosions_stop_at_the_limit() {
+        // a d1 always explodes, until it has rolled MAX_EXPLOSIONS extra times like in RollingSystem
+        let distribution = DamageDistribution::of_attack(&[Die::red(1).exploding()]);
+        assert_distribution(&distribution, &[(MAX_EXPLOSIONS + 1, 1.0)]);
+    }
+
+    #[test]
+    fn prepped_dice_count_for_what_they_rolled() {
+        let mut prepped = Die::red(6).exploding();
+        prepped.rolled_value = Some(Face::Number(6));
+        prepped.exploded = vec![Face::Number(2)];
+        let distribution = DamageDistribution::of_attack(&[prepped, Die::red(2)]);
+        assert_distribution(&distribution, &[(9, 0.5), (10, 0.5)]);
+        // the target's blocked damage has to be dealt on top of their HP
+        assert!((distribution.chance_to_kill(5, 5) - 0.5).abs() < 1e-9);
+        assert!((distribution.chance_to_kill(5, 3) - 1.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,113 @@
+use crate::campaign::*;
+use crate::combat_state::*;
+use crate::combatant::*;
+use crate::components::*;
+use crate::events::TurnStarted;
+use crate::log::CombatLog;
+use crate::shared::LogEntry;
+use serde::{Deserialize, Serialize};
+use specs::shrev::EventChannel;
+use specs::{Entity, World, WorldExt};
+use std::fs;
+
+pub const PVP_ENCOUNTER_FILE: &str = "assets/pvp.json";
+pub const SEATS: usize = 2;
+
+/// Ids of the players sitting in each seat of a PvP match, if they identified themselves.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Seats {
+    pub players: Vec<Option<String>>,
+}
+
+/// Data file definition of a PvP match: one team of combatants for each seat.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PvpEncounter {
+    pub name: String,
+    pub teams: Vec<Vec<CombatantDef>>,
+}
+
+impl PvpEncounter {
+    /// Read and validate an encounter file, once when the server starts like `Campaign::load`.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let data = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read encounter file {}: {}", path, err))?;
+        let encounter: PvpEncounter = serde_json::from_str(&data)
+            .map_err(|err| format!("Could not parse encounter file {}: {}", path, err))?;
+        if encounter.teams.len() != SEATS || encounter.teams.iter().any(Vec::is_empty) {
+            return Err(format!(
+                "Encounter file {} needs {} non-empty teams",
+                path, SEATS
+            ));
+        }
+        for def in encounter.teams.iter().flatten() {
+            def.validate()
+                .map_err(|err| format!("Invalid encounter file {}: {}", path, err))?;
+        }
+        Ok(encounter)
+    }
+
+    /// A match is played as a campaign run with a single battle, where the first seat's team is the
+    /// party and the second seat's team are the enemies.
+    pub fn to_campaign(&self) -> Campaign {
+        Campaign {
+            name: self.name.clone(),
+            party: self.teams[0].clone(),
+            enemies: self.teams[1].clone(),
+            floors: vec![],
+        }
+    }
+}
+
+/// Start a fresh match between the party and enemies of the given campaign, throwing away the previous one.
+pub fn start_match(world: &mut World, campaign: Campaign) {
+    world.delete_all();
+    world.maintain();
+
+    let party: Vec<Entity> = campaign
+        .party
+        .iter()
+        .map(|def| create_combatant(world, def, false))
+        .collect();
+    let enemies: Vec<Entity> = campaign
+        .enemies
+        .iter()
+        .map(|def| create_combatant(world, def, true))
+        .collect();
+    // seats take turns: the first combatant of each team, then the second of each team, and so on
+    let mut combatants = vec![];
+    for n in 0..party.len().max(enemies.len()) {
+        combatants.extend(party.get(n));
+        combatants.extend(enemies.get(n));
+    }
+    let combat_state = CombatState::new(combatants);
+
+    let mut combat_log = CombatLog {
+        ..Default::default()
+    };
+    combat_log.add(LogEntry::EnteredNode(campaign.name.clone()));
+    world
+        .write_resource::<EventChannel<TurnStarted>>()
+        .single_write(TurnStarted {
+            round: combat_state.round,
+            combatant: combat_state.combatants[0],
+        });
+    world.insert(combat_state);
+    world.insert(combat_log);
+    world.insert(Run {
+        visited: vec![campaign.name.clone()],
+        campaign,
+        party,
+        phase: RunPhase::InNode,
+        floor: 0,
+        encounters_won: 0,
+    });
+}
+
+/// Which seat controls the given combatant.
+pub fn seat_of(world: &World, combatant: Entity) -> usize {
+    if world.read_storage::<Enemy>().contains(combatant) {
+        1
+    } else {
+        0
+    }
+}
@@ -0,0 +1,210 @@
+use crate::room::RoomSettings;
+use crate::shared::*;
+
+/// Whose point of view a state is sent from, which decides the hidden information they get to see.
+pub enum Viewer {
+    Everything,  // e.g. the player in a campaign run, who controls the whole party
+    Side(usize), // only the hidden information of one side's combatants, e.g. a PvP player's own team
+    Nothing,     // e.g. a masked spectator
+}
+
+impl Viewer {
+    fn sees(&self, side: usize) -> bool {
+        match self {
+            Viewer::Everything => true,
+            Viewer::Side(seen) => *seen == side,
+            Viewer::Nothing => false,
+        }
+    }
+}
+
+/// Hide whatever the viewer isn't allowed to see from the materialized state. Dice pools are always
+/// private, leaving only the color of each die. With `face_down_prep`, rolled and prepped dice stay face
+/// down too, until they get used in an attack.
+pub fn redact(
+    state: &ClientGameState,
+    viewer: &Viewer,
+    settings: &RoomSettings,
+) -> ClientGameState {
+    let mut state = state.clone();
+    if let Viewer::Everything = viewer {
+        return state;
+    }
+    let face_down = settings.face_down_prep;
+    let current_seen = state
+        .current_combatant
+        .and_then(|n| state.combatants.get(n))
+        .is_some_and(|combatant| viewer.sees(combatant.side));
+
+    state.client_phase = match state.client_phase {
+        ClientPhase::DraftDice(dice, max) if !current_seen => {
+            ClientPhase::DraftDice(hide_dice(&dice), max)
+        }
+        // rewards only come up in campaign runs, so there's no saying which side is choosing
+        ClientPhase::ChooseReward(rewards, dice) => {
+            ClientPhase::ChooseReward(rewards, hide_dice(&dice))
+        }
+        ClientPhase::SelectAction(dice, actions) if face_down && !current_seen => {
+            ClientPhase::SelectAction(hide_dice(&dice), actions.iter().map(hide_action).collect())
+        }
+        ClientPhase::SelectTarget(dice, action, targets) if face_down && !current_seen => {
+            ClientPhase::SelectTarget(hide_dice(&dice), hide_action(&action), targets)
+        }
+        client_phase => client_phase,
+    };
+
+    for combatant in state.combatants.iter_mut() {
+        if viewer.sees(combatant.side) {
+            continue;
+        }
+        combatant.pool = hide_dice(&combatant.pool);
+        if face_down {
+            combatant.prepped_attack = hide_dice(&combatant.prepped_attack);
+            combatant.prepped_defense = hide_dice(&combatant.prepped_defense);
+        }
+    }
+
+    // by side rather than by name, since combatants on both sides can share a name
+    let hidden = |side: usize| !viewer.sees(side);
+    state.combat_log = state
+        .combat_log
+        .into_iter()
+        .filter_map(|entry| match entry {
+            LogEntry::Drafted {
+                combatant,
+                side,
+                die,
+            } if hidden(side) => Some(LogEntry::Drafted {
+                die: Die::hidden(die.color),
+                combatant,
+                side,
+            }),
+            LogEntry::Rolled {
+                combatant,
+                side,
+                dice,
+            } if face_down && hidden(side) => Some(LogEntry::Rolled {
+                dice: hide_dice(&dice),
+                combatant,
+                side,
+            }),
+            LogEntry::Prepped {
+                combatant,
+                side,
+                prep,
+                dice,
+            } if face_down && hidden(side) => Some(LogEntry::Prepped {
+                dice: hide_dice(&dice),
+                combatant,
+                side,
+                prep,
+            }),
+            // an explosion would give away the rolled face
+            LogEntry::Exploded { side, .. } if face_down && hidden(side) => None,
+            entry => Some(entry),
+        })
+        .collect();
+    state
+}
+
+fn hide_dice(dice: &[Die]) -> Vec<Die> {
+    dice.iter().map(|die| Die::hidden(die.color)).collect()
+}
+
+fn hide_action(action: &ClientAction) -> ClientAction {
+    match action {
+        ClientAction::HeavyAttack(dice) => ClientAction::HeavyAttack(hide_dice(dice)),
+        action => action.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn combatant(side: usize) -> ClientCombatant {
+        ClientCombatant {
+            name: "Squire".to_owned(),
+            hp: 10,
+            max_hp: 10,
+            side,
+            pool: vec![Die::red(6)],
+            prepped_attack: vec![],
+            prepped_defense: vec![Die::blue(6)],
+        }
+    }
+
+    fn drafted(side: usize) -> LogEntry {
+        LogEntry::Drafted {
+            combatant: "Squire".to_owned(),
+            side,
+            die: Die::red(6),
+        }
+    }
+
+    fn prepped(side: usize) -> LogEntry {
+        LogEntry::Prepped {
+            combatant: "Squire".to_owned(),
+            side,
+            prep: Prep::Defense,
+            dice: vec![Die::blue(6)],
+        }
+    }
+
+    fn hidden_dice(entry: &LogEntry) -> bool {
+        match entry {
+            LogEntry::Drafted { die, .. } => die.is_hidden(),
+            LogEntry::Prepped { dice, .. } => dice.iter().all(Die::is_hidden),
+            entry => panic!("unexpected entry {:?}", entry),
+        }
+    }
+
+    /// Two combatants with the same name, one on each side, who both drafted and prepped a die.
+    fn mirror_match() -> ClientGameState {
+        ClientGameState {
+            client_phase: ClientPhase::Waiting,
+            combatants: vec![combatant(0), combatant(1)],
+            current_combatant: Some(0),
+            turn_time_left: None,
+            spectating: false,
+            seat: None,
+            combat_log: vec![drafted(0), prepped(0), drafted(1), prepped(1)],
+        }
+    }
+
+    #[test]
+    fn same_named_combatants_are_redacted_by_side() {
+        let settings = RoomSettings {
+            face_down_prep: true,
+            ..RoomSettings::default()
+        };
+        for seat in 0..2 {
+            let state = redact(&mirror_match(), &Viewer::Side(seat), &settings);
+            for (n, combatant) in state.combatants.iter().enumerate() {
+                assert_eq!(combatant.pool[0].is_hidden(), n != seat);
+                assert_eq!(combatant.prepped_defense[0].is_hidden(), n != seat);
+            }
+            let hidden: Vec<bool> = state.combat_log.iter().map(hidden_dice).collect();
+            assert_eq!(hidden, [seat != 0, seat != 0, seat != 1, seat != 1]);
+        }
+    }
+
+    #[test]
+    fn masked_spectators_see_nobody_and_others_see_everything() {
+        let settings = RoomSettings {
+            face_down_prep: true,
+            ..RoomSettings::default()
+        };
+        let masked = redact(&mirror_match(), &Viewer::Nothing, &settings);
+        assert!(masked.combat_log.iter().all(hidden_dice));
+        let everything = redact(&mirror_match(), &Viewer::Everything, &settings);
+        assert!(!everything.combat_log.iter().any(hidden_dice));
+    }
+
+    #[test]
+    fn prepped_dice_are_only_hidden_face_down() {
+        let state = redact(&mirror_match(), &Viewer::Side(0), &RoomSettings::default());
+        let hidden: Vec<bool> = state.combat_log.iter().map(hidden_dice).collect();
+        assert_eq!(hidden, [false, false, true, false]);
+    }
+}
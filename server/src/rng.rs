@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_INC: u64 = 1442695040888963407;
+const MULTIPLIER: u64 = 6364136223846793005;
+
+/// Seedable random number generator (the same PCG as quad-rand), kept as a world resource
+/// so that its state can be saved and restored along with the rest of the battle.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DiceRng {
+    pub state: u64,
+}
+
+impl DiceRng {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = DiceRng { state: 0 };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let oldstate = self.state;
+        self.state = oldstate.wrapping_mul(MULTIPLIER).wrapping_add(DEFAULT_INC);
+        let xorshifted = (((oldstate >> 18) ^ oldstate) >> 27) as u32;
+        let rot = (oldstate >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    /// Random number in `low..high`.
+    pub fn gen_range(&mut self, low: usize, high: usize) -> usize {
+        low + ((u64::from(self.next_u32()) * (high - low) as u64) >> 32) as usize
+    }
+
+    pub fn choose<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.gen_range(0, items.len())]
+    }
+}
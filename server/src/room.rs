@@ -0,0 +1,549 @@
+use crate::ai;
+use crate::campaign::*;
+use crate::combat_state::*;
+use crate::components::*;
+use crate::config::Config;
+use crate::events::*;
+use crate::log::*;
+use crate::metrics;
+use crate::players::FinishedMatch;
+use crate::pvp::*;
+use crate::redaction::*;
+use crate::rng::DiceRng;
+use crate::save::*;
+use crate::shared::*;
+use crate::systems::*;
+use serde::{Deserialize, Serialize};
+use specs::shrev::EventChannel;
+use specs::Dispatcher;
+use specs::RunNow;
+use specs::World;
+use specs::WorldExt;
+use std::cmp::Reverse;
+use std::fmt::Debug;
+use std::io;
+use std::mem::discriminant;
+use std::mem::Discriminant;
+use std::time::Duration;
+use std::time::Instant;
+use tracing::{error, info, info_span, trace, warn, Span};
+use ws::util::Token;
+use ws::Sender;
+
+pub const DEFAULT_ROOM: &str = "default";
+pub const DEFAULT_TURN_TIMER: u64 = 60;
+// ws timeout token for checking whether a room's turn timer ran out
+pub const TURN_TIMER: Token = Token(1);
+// a turn takes a few messages (draft, action, target), this is just a safety net
+const MAX_AI_MESSAGES: usize = 5;
+// a room nobody has been in for this long is thrown away once the server needs space for another room
+const ABANDONED_ROOM_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// Settings chosen by whoever creates a room, which are saved along with it.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct RoomSettings {
+    pub turn_timer: Option<u64>, // seconds a player gets for each phase of their turn, before the AI plays it for them
+    pub mask_spectators: bool, // spectators only see what an opponent would, instead of everything
+    pub face_down_prep: bool, // rolled and prepped dice are hidden from opponents until they're used
+    pub pvp: bool, // two players fight each other, instead of the party going on a campaign run
+}
+impl Default for RoomSettings {
+    fn default() -> Self {
+        RoomSettings {
+            turn_timer: Some(DEFAULT_TURN_TIMER),
+            mask_spectators: false,
+            face_down_prep: false,
+            pvp: false,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Role {
+    Player(Option<usize>), // seat in a PvP match, while campaign runs have a single player controlling everyone
+    Spectator,             // receives state, but can't act
+}
+
+pub struct Client {
+    pub sender: Sender,
+    pub role: Role,
+}
+
+/// A single game, with its own specs world and the connections taking part in it.
+pub struct Room {
+    pub code: String,
+    pub world: specs::World,
+    dispatcher: Dispatcher<'static, 'static>,
+    // full state of the room, which `client_state` redacts for each viewer
+    pub materialized_state: ClientGameState,
+    pub clients: Vec<Client>,
+    // when the current phase of the turn runs out of time, if the room has a turn timer
+    pub turn_deadline: Option<Instant>,
+    timed_phase: Option<(Discriminant<ClientPhase>, Option<usize>, usize)>,
+    // client whose connection checks the turn timer, and the deadline it was scheduled for
+    timer_client: Option<(Token, Instant)>,
+    // result of the PvP match that just ended, until it gets recorded
+    finished_match: Option<FinishedMatch>,
+    // new dice won as campaign rewards, until they get added to the player's profile
+    unlocked_dice: Vec<Die>,
+    max_log_entries: Option<usize>,
+    save_dir: String,
+    // when the game last moved on, to tell abandoned rooms apart
+    last_active: Instant,
+}
+
+impl Room {
+    /// Start a room playing the given campaign, which is a PvP match's encounter if the settings ask for PvP.
+    pub fn new(
+        code: &str,
+        seed: u64,
+        settings: RoomSettings,
+        campaign: Campaign,
+        config: &Config,
+    ) -> Self {
+        // systems start reading events before the first turn starts
+        let (mut world, dispatcher) = create_world();
+        world.insert(DiceRng::new(seed));
+        world.insert(settings);
+        if settings.pvp {
+            start_match(&mut world, campaign);
+        } else {
+            start_run(&mut world, campaign);
+        }
+        info!(room = code, "Created new game world");
+        Room::from_world(code, world, dispatcher, config)
+    }
+
+    pub fn from_save(code: &str, save: SaveFile, config: &Config) -> Self {
+        let (mut world, dispatcher) = create_world();
+        save.restore(&mut world);
+        info!(room = code, "Restored game world from save");
+        Room::from_world(code, world, dispatcher, config)
+    }
+
+    fn from_world(
+        code: &str,
+        mut world: World,
+        dispatcher: Dispatcher<'static, 'static>,
+        config: &Config,
+    ) -> Self {
+        let initial_state = get_materialized_state(&mut world);
+        let mut room = Room {
+            code: code.to_owned(),
+            world,
+            dispatcher,
+            materialized_state: initial_state,
+            clients: vec![],
+            turn_deadline: None,
+            timed_phase: None,
+            timer_client: None,
+            finished_match: None,
+            unlocked_dice: vec![],
+            max_log_entries: Some(config.max_log_entries).filter(|entries| *entries > 0),
+            save_dir: config.save_dir.clone(),
+            last_active: Instant::now(),
+        };
+        room.update();
+        room
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        save_room(&self.save_dir, &self.code, &self.world)
+    }
+
+    /// Delete the room's save, once the room is thrown away.
+    pub fn delete_save(&self) -> io::Result<()> {
+        delete_room(&self.save_dir, &self.code)
+    }
+
+    /// Whether the run or match is over, and nobody has started another one yet.
+    pub fn is_over(&self) -> bool {
+        matches!(
+            self.world.read_resource::<Run>().phase,
+            RunPhase::Finished(_) | RunPhase::Ended
+        )
+    }
+
+    /// Whether the room can be thrown away: nobody is in it, and either its game is over or it
+    /// hasn't moved on in a long time.
+    pub fn is_abandoned(&self) -> bool {
+        self.clients.is_empty()
+            && (self.is_over() || self.last_active.elapsed() >= ABANDONED_ROOM_TIMEOUT)
+    }
+
+    /// Span for everything logged while running the room's game.
+    pub fn span(&self) -> Span {
+        info_span!("room", room = %self.code)
+    }
+
+    /// Run every system once, for the events sent since the last update (e.g. by a client's message), and
+    /// leave the current node of the campaign once it's over. The systems are ordered so that a single run
+    /// moves the battle through every phase it can get to without another message (see `game_systems`),
+    /// so the room is always left in a phase that waits for a player.
+    pub fn update(&mut self) {
+        let span = self.span();
+        let _span = span.enter();
+        self.last_active = Instant::now();
+        let was_over = matches!(
+            self.materialized_state.client_phase,
+            ClientPhase::MatchResult(_)
+        );
+        let logged = self.world.read_resource::<CombatLog>().total_added();
+
+        self.dispatcher.dispatch(&self.world);
+        self.world.maintain();
+        metrics::count(&metrics::SYSTEM_RUNS);
+
+        // once the current node of the campaign is over, the party moves on through the map
+        let encounter_over = matches!(
+            self.world.read_resource::<CombatState>().current_phase,
+            CombatPhase::EncounterOver
+        );
+        if encounter_over {
+            finish_node(&mut self.world);
+        }
+        if let Some(max_entries) = self.max_log_entries {
+            self.world.write_resource::<CombatLog>().trim(max_entries);
+        }
+
+        {
+            let combat_log = self.world.read_resource::<CombatLog>();
+            let new_entries = (combat_log.total_added() - logged).min(combat_log.entries.len());
+            let entries = &combat_log.entries[combat_log.entries.len() - new_entries..];
+            self.unlocked_dice
+                .extend(entries.iter().filter_map(|entry| match entry {
+                    LogEntry::Reward {
+                        reward: Reward::NewDie(die),
+                        ..
+                    } => Some(die.clone()),
+                    _ => None,
+                }));
+        }
+        let mut materialize_system = MaterializeSystem {};
+        materialize_system.run_now(&self.world);
+        self.materialized_state = get_materialized_state(&mut self.world);
+
+        if let ClientPhase::MatchResult(result) = &self.materialized_state.client_phase {
+            // matches ended early by an admin have no winner, so there's nothing to record
+            if let (false, Some(winner)) = (was_over, result.winner) {
+                self.finished_match = Some(FinishedMatch {
+                    room: self.code.clone(),
+                    encounter: result.encounter.clone(),
+                    players: self.world.read_resource::<Seats>().players.clone(),
+                    winner,
+                    replay: self.world.read_resource::<CombatLog>().entries.clone(),
+                });
+            }
+        }
+        self.update_turn_timer();
+    }
+
+    /// Restart the turn timer whenever a new phase of a turn starts, and stop it outside of turns.
+    fn update_turn_timer(&mut self) {
+        let state = &self.materialized_state;
+        let timed_phase = match state.client_phase {
+            ClientPhase::DraftDice(..)
+            | ClientPhase::SelectAction(..)
+            | ClientPhase::SelectTarget(..) => Some((
+                discriminant(&state.client_phase),
+                state.current_combatant,
+                self.world.read_resource::<CombatLog>().total_added(),
+            )),
+            _ => None,
+        };
+        if timed_phase != self.timed_phase {
+            let seconds = self.world.read_resource::<RoomSettings>().turn_timer;
+            self.turn_deadline = match (timed_phase, seconds) {
+                (Some(_), Some(seconds)) => Some(Instant::now() + Duration::from_secs(seconds)),
+                _ => None,
+            };
+            self.timed_phase = timed_phase;
+        }
+    }
+
+    /// Once the turn timer runs out, the AI finishes the current combatant's turn for them.
+    /// Returns true if the state changed.
+    pub fn check_turn_timer(&mut self) -> bool {
+        match self.turn_deadline {
+            Some(deadline) if Instant::now() >= deadline => {}
+            _ => return false,
+        }
+        let turn = |world: &World| {
+            let combat_state = world.read_resource::<CombatState>();
+            (combat_state.round, combat_state.current_character)
+        };
+        let timed_out_turn = turn(&self.world);
+        {
+            let combat_state = self.world.read_resource::<CombatState>();
+            let current_entity = combat_state.combatants[combat_state.current_character];
+            let name = self
+                .world
+                .read_storage::<Named>()
+                .get(current_entity)
+                .unwrap()
+                .name
+                .clone();
+            info!(combatant = %name, "Turn timer ran out");
+            self.world
+                .write_resource::<CombatLog>()
+                .add(LogEntry::TimedOut { combatant: name });
+        }
+        for _ in 0..MAX_AI_MESSAGES {
+            if turn(&self.world) != timed_out_turn {
+                break;
+            }
+            match ai::next_message(&self.world) {
+                Some(msg) => self.handle_message(msg),
+                None => break,
+            }
+        }
+        // make sure the timer restarts, even if the AI couldn't do anything
+        self.timed_phase = None;
+        self.update();
+        true
+    }
+
+    /// Ask a client to check the turn timer once it's due to run out. Only done when the deadline changes,
+    /// or the client that was asked leaves, so there's a single timeout for each deadline. Timeouts for
+    /// earlier deadlines that still fire are ignored by `check_turn_timer`, since the new deadline isn't up.
+    pub fn schedule_turn_timer(&mut self) {
+        let deadline = match self.turn_deadline {
+            Some(deadline) => deadline,
+            None => {
+                self.timer_client = None;
+                return;
+            }
+        };
+        if let Some((token, scheduled)) = self.timer_client {
+            let still_here = self
+                .clients
+                .iter()
+                .any(|client| client.sender.token() == token);
+            if scheduled == deadline && still_here {
+                return;
+            }
+        }
+        // with nobody in the room, the timer is scheduled once someone joins
+        self.timer_client = None;
+        if let Some(client) = self.clients.first() {
+            let ms = deadline
+                .saturating_duration_since(Instant::now())
+                .as_millis() as u64;
+            match client.sender.timeout(ms + 100, TURN_TIMER) {
+                Ok(()) => self.timer_client = Some((client.sender.token(), deadline)),
+                Err(err) => error!(%err, "Could not schedule turn timer"),
+            }
+        }
+    }
+
+    /// End the run or match right away, without a winner, e.g. when a battle is stuck.
+    /// Returns false if it was already over.
+    pub fn force_end(&mut self) -> bool {
+        {
+            let mut run = self.world.write_resource::<Run>();
+            if matches!(run.phase, RunPhase::Finished(_) | RunPhase::Ended) {
+                return false;
+            }
+            run.phase = RunPhase::Ended;
+            let mut combat_state = self.world.write_resource::<CombatState>();
+            combat_state.transition(Transition::EndEncounter);
+            let mut combat_log = self.world.write_resource::<CombatLog>();
+            combat_log.add(LogEntry::BattleEnded);
+        }
+        self.update();
+        true
+    }
+
+    /// Result of the PvP match that just ended in this room, if it hasn't been recorded yet.
+    pub fn take_finished_match(&mut self) -> Option<FinishedMatch> {
+        self.finished_match.take()
+    }
+
+    /// New dice won as campaign rewards since this was last called, for the player's profile.
+    pub fn take_unlocked_dice(&mut self) -> Vec<Die> {
+        std::mem::take(&mut self.unlocked_dice)
+    }
+
+    /// Add a client to the room. Players get the seat they asked for in a PvP match, or else the seat
+    /// they had before, or else the first free seat, or become spectators if no seat is free.
+    /// Seats that belong to another player (see `seat_owner`) are never free. Returns the role they ended up with.
+    pub fn join(&mut self, sender: Sender, role: Role, player: Option<String>) -> Role {
+        let taken = |seat: usize| {
+            self.clients
+                .iter()
+                .any(|client| client.role == Role::Player(Some(seat)))
+        };
+        let owned_by = |seat: usize, player: &Option<String>| {
+            player.is_some() && self.seat_owner(seat) == *player
+        };
+        let free = |seat: usize| {
+            !taken(seat) && (self.seat_owner(seat).is_none() || owned_by(seat, &player))
+        };
+        let role = match role {
+            Role::Player(seat) if self.world.read_resource::<RoomSettings>().pvp => seat
+                .filter(|seat| free(*seat))
+                .or_else(|| (0..SEATS).find(|seat| owned_by(*seat, &player) && free(*seat)))
+                .or_else(|| (0..SEATS).find(|seat| free(*seat)))
+                .map_or(Role::Spectator, |seat| Role::Player(Some(seat))),
+            // there's only one side to play in a campaign run
+            Role::Player(_) => Role::Player(None),
+            role => role,
+        };
+        if let Role::Player(Some(seat)) = role {
+            let mut seats = self.world.write_resource::<Seats>();
+            seats.players.resize(SEATS, None);
+            seats.players[seat] = player;
+        }
+        self.clients.push(Client { sender, role });
+        role
+    }
+
+    /// Player a PvP seat belongs to: the one matchmaking put there, or else whoever first sat in it.
+    /// Nobody else can take the seat, even after its player leaves, so their match (and rating) stays theirs.
+    pub fn seat_owner(&self, seat: usize) -> Option<String> {
+        let seats = self.world.read_resource::<Seats>();
+        seats.players.get(seat).cloned().flatten()
+    }
+
+    /// Keep each seat of a new PvP match for the given player, e.g. the two paired up by matchmaking.
+    pub fn reserve_seats(&mut self, players: Vec<Option<String>>) {
+        self.world.write_resource::<Seats>().players = players;
+    }
+
+    /// Check whether a client in the given role is allowed to send this message.
+    pub fn check_message(&self, role: Role, client_msg: &ClientMessage) -> Result<(), String> {
+        match (role, client_msg) {
+            (Role::Spectator, _) => Err("Spectators can't take actions".to_owned()),
+            // either player can ask for a rematch once the match is over
+            (Role::Player(_), ClientMessage::StartRun) => Ok(()),
+            (Role::Player(Some(seat)), _) => {
+                let combat_state = self.world.read_resource::<CombatState>();
+                let current_entity = combat_state.combatants[combat_state.current_character];
+                if seat_of(&self.world, current_entity) == seat {
+                    Ok(())
+                } else {
+                    Err("It's not your turn".to_owned())
+                }
+            }
+            (Role::Player(None), _) => Ok(()),
+        }
+    }
+
+    /// State to send to a client in the given role, with the time left on the turn timer filled in.
+    pub fn client_state(&self, role: Role) -> ClientGameState {
+        let settings = self.world.read_resource::<RoomSettings>();
+        let viewer = match role {
+            // the one player in a campaign run controls the whole party
+            Role::Player(None) => Viewer::Everything,
+            Role::Player(Some(seat)) => Viewer::Side(seat),
+            Role::Spectator if settings.mask_spectators => Viewer::Nothing,
+            Role::Spectator => Viewer::Everything,
+        };
+        let mut state = redact(&self.materialized_state, &viewer, &settings);
+        state.spectating = role == Role::Spectator;
+        state.seat = match role {
+            Role::Player(seat) => seat,
+            Role::Spectator => None,
+        };
+        state.turn_time_left = self.turn_deadline.map(|deadline| {
+            // round up, so the client doesn't show 0 seconds while there's still time left
+            let ms = deadline
+                .saturating_duration_since(Instant::now())
+                .as_millis() as u64;
+            ms.div_ceil(1000)
+        });
+        state
+    }
+
+    /// Send the current state to every client in the room.
+    pub fn broadcast(&self) {
+        for client in self.clients.iter() {
+            let server_msg = ServerMessage::NewState(self.client_state(client.role));
+            if let Err(err) = client
+                .sender
+                .send(serde_json::to_string(&server_msg).unwrap())
+            {
+                warn!(%err, "Could not send state to client");
+            }
+        }
+    }
+
+    /// Handle a message from a client in the given role, unless they aren't allowed to send it.
+    pub fn receive(&mut self, role: Role, client_msg: ClientMessage) -> Result<(), String> {
+        self.check_message(role, &client_msg)?;
+        self.handle_message(client_msg);
+        Ok(())
+    }
+
+    pub fn handle_message(&mut self, client_msg: ClientMessage) {
+        // dispatch event/etc. based on incoming message
+        match client_msg {
+            ClientMessage::FinishDrafting(draft_choices) => {
+                // User is finished drafting. Send all their draft choices into our Drafting system.
+                // process draft choices in reverse index order so we don't invalidate the indexes
+                let mut sorted_choices = draft_choices;
+                sorted_choices.sort_by_key(|&b| Reverse(b));
+                sorted_choices.dedup();
+                for choice in sorted_choices {
+                    self.send(DraftDie(choice));
+                }
+                // DraftingSystem drafts all the dice before it moves on to rolling them
+                self.send(FinishDrafting);
+            }
+            ClientMessage::ChooseReward(reward, die) => self.send(ChooseReward(reward, die)),
+            ClientMessage::ChooseAction(action) => self.send(ChooseAction(action)),
+            ClientMessage::ChooseTarget(target) => self.send(ChooseTarget(target)),
+            ClientMessage::CancelAction => self.send(CancelAction),
+            ClientMessage::ChooseNode(node) => enter_node(&mut self.world, node),
+            // matchmaking happens outside of any room
+            ClientMessage::FindMatch | ClientMessage::CancelFindMatch => {}
+            // profiles aren't part of any room either
+            ClientMessage::SetName(_) => {}
+            ClientMessage::StartRun => {
+                let campaign = {
+                    let run = self.world.read_resource::<Run>();
+                    match run.phase {
+                        RunPhase::Finished(_) | RunPhase::Ended => Some(run.campaign.clone()),
+                        _ => None,
+                    }
+                };
+                if let Some(campaign) = campaign {
+                    if self.world.read_resource::<RoomSettings>().pvp {
+                        start_match(&mut self.world, campaign);
+                    } else {
+                        start_run(&mut self.world, campaign);
+                    }
+                }
+            }
+        }
+
+        self.update();
+    }
+
+    /// Send an event to whichever systems read it, on the next update.
+    fn send<E: Debug + Send + Sync + 'static>(&mut self, event: E) {
+        trace!(?event, "Sent event");
+        self.world
+            .write_resource::<EventChannel<E>>()
+            .single_write(event);
+    }
+}
+
+/// Setup a specs world along with the dispatcher running its systems. Setting up the dispatcher registers
+/// the components and resources the systems use, so only resources outside of any system are inserted here.
+fn create_world() -> (World, Dispatcher<'static, 'static>) {
+    let mut world = World::new();
+    let mut dispatcher = game_systems().build();
+    dispatcher.setup(&mut world);
+
+    // Insert global resources
+    world.insert(Seats {
+        ..Default::default()
+    });
+    (world, dispatcher)
+}
+
+fn get_materialized_state(world: &mut specs::World) -> ClientGameState {
+    let combat_state = world.read_resource::<CombatState>();
+    combat_state.materialized_state.clone()
+}
@@ -0,0 +1,302 @@
+use crate::campaign::*;
+use crate::combat_state::*;
+use crate::components::*;
+use crate::log::CombatLog;
+use crate::pvp::Seats;
+use crate::rng::DiceRng;
+use crate::room::RoomSettings;
+use crate::shared::Die;
+use serde::{Deserialize, Serialize};
+use specs::{Builder, Entity, World, WorldExt};
+use std::fs;
+use std::io;
+use std::path::Path;
+use tracing::error;
+
+/// Bump this whenever the save format changes. Saves from other versions are not restored.
+pub const SAVE_VERSION: u32 = 1;
+// default directory for saves, relative to the working directory
+pub const SAVE_DIR: &str = "saves";
+
+/// Snapshot of a room's whole battle: every combatant entity and all global resources.
+/// Entities can't be saved directly, so they are referred to by their position in `combatants`.
+#[derive(Serialize, Deserialize)]
+pub struct SaveFile {
+    pub version: u32,
+    pub combatants: Vec<SavedCombatant>,
+    pub combat_state: SavedCombatState,
+    pub run: SavedRun,
+    pub combat_log: CombatLog,
+    pub rng: DiceRng,
+    pub settings: RoomSettings,
+    pub seats: Seats,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedCombatant {
+    pub named: Option<Named>,
+    pub health: Option<Health>,
+    pub dice_pool: Option<DicePool>,
+    // marker components are saved as flags, since unit structs serialize to `null`
+    pub light_attacker: bool,
+    pub heavy_attacker: Option<HeavyAttacker>,
+    pub defender: Option<Defender>,
+    pub enemy: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedCombatState {
+    pub round: usize,
+    pub current_character: usize,
+    pub combatants: Vec<usize>,
+    pub current_phase: CombatPhase,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedRun {
+    pub campaign: Campaign,
+    pub party: Vec<usize>,
+    pub phase: RunPhase,
+    pub floor: usize,
+    pub encounters_won: usize,
+    pub visited: Vec<String>,
+}
+
+impl SaveFile {
+    pub fn from_world(world: &World) -> Self {
+        let combat_state = world.read_resource::<CombatState>();
+        let run = world.read_resource::<Run>();
+
+        // The party always comes first, followed by whoever else is in the current battle
+        let mut entities: Vec<Entity> = run.party.clone();
+        for combatant in combat_state.combatants.iter() {
+            if !entities.contains(combatant) {
+                entities.push(*combatant);
+            }
+        }
+        let index_of = |entity: &Entity| entities.iter().position(|e| e == entity).unwrap();
+
+        SaveFile {
+            version: SAVE_VERSION,
+            combatants: entities
+                .iter()
+                .map(|entity| SavedCombatant {
+                    named: world.read_storage::<Named>().get(*entity).cloned(),
+                    health: world.read_storage::<Health>().get(*entity).cloned(),
+                    dice_pool: world.read_storage::<DicePool>().get(*entity).cloned(),
+                    light_attacker: world.read_storage::<LightAttacker>().contains(*entity),
+                    heavy_attacker: world.read_storage::<HeavyAttacker>().get(*entity).cloned(),
+                    defender: world.read_storage::<Defender>().get(*entity).cloned(),
+                    enemy: world.read_storage::<Enemy>().contains(*entity),
+                })
+                .collect(),
+            combat_state: SavedCombatState {
+                round: combat_state.round,
+                current_character: combat_state.current_character,
+                combatants: combat_state.combatants.iter().map(index_of).collect(),
+                current_phase: combat_state.current_phase.clone(),
+            },
+            run: SavedRun {
+                campaign: run.campaign.clone(),
+                party: run.party.iter().map(index_of).collect(),
+                phase: run.phase,
+                floor: run.floor,
+                encounters_won: run.encounters_won,
+                visited: run.visited.clone(),
+            },
+            combat_log: (*world.read_resource::<CombatLog>()).clone(),
+            rng: (*world.read_resource::<DiceRng>()).clone(),
+            settings: *world.read_resource::<RoomSettings>(),
+            seats: (*world.read_resource::<Seats>()).clone(),
+        }
+    }
+
+    /// Recreate the saved entities and resources in a world that has its components registered.
+    /// The save has to be valid (see `validate`), which `load_rooms` checks.
+    pub fn restore(self, world: &mut World) {
+        let entities: Vec<Entity> = self
+            .combatants
+            .into_iter()
+            .map(|saved| restore_combatant(world, saved))
+            .collect();
+
+        let mut combat_state = CombatState::new(
+            self.combat_state
+                .combatants
+                .iter()
+                .map(|n| entities[*n])
+                .collect(),
+        );
+        combat_state.round = self.combat_state.round;
+        combat_state.current_character = self.combat_state.current_character;
+        combat_state.current_phase = self.combat_state.current_phase;
+        world.insert(combat_state);
+        world.insert(Run {
+            campaign: self.run.campaign,
+            party: self.run.party.iter().map(|n| entities[*n]).collect(),
+            phase: self.run.phase,
+            floor: self.run.floor,
+            encounters_won: self.run.encounters_won,
+            visited: self.run.visited,
+        });
+        world.insert(self.combat_log);
+        world.insert(self.rng);
+        world.insert(self.settings);
+        world.insert(self.seats);
+    }
+}
+
+impl SaveFile {
+    /// Check that everything refers to things that are in the save, so restoring it can't panic
+    /// (or leave the room stuck) on a save that parses but was corrupted.
+    pub fn validate(&self) -> Result<(), String> {
+        self.run.campaign.validate()?;
+        let entities = self.combatants.len();
+        if self.combatants.iter().any(|saved| {
+            saved.named.is_none() || saved.health.is_none() || saved.dice_pool.is_none()
+        }) {
+            return Err("save has a combatant without a name, health or dice pool".to_owned());
+        }
+        // dice without faces can't be rolled
+        if self
+            .combatants
+            .iter()
+            .flat_map(SavedCombatant::dice)
+            .any(|die| die.faces.is_empty())
+        {
+            return Err("save has a die without any faces".to_owned());
+        }
+        let combat_state = &self.combat_state;
+        if combat_state
+            .combatants
+            .iter()
+            .chain(self.run.party.iter())
+            .any(|n| *n >= entities)
+        {
+            return Err("save refers to a combatant it doesn't have".to_owned());
+        }
+        // phases refer to combatants by their position in the battle
+        let in_battle = combat_state.combatants.len();
+        let phase_positions = match &combat_state.current_phase {
+            CombatPhase::SelectAction(actions) => actions
+                .iter()
+                .filter_map(|(_, action)| action.target())
+                .collect(),
+            CombatPhase::SelectTarget(action) | CombatPhase::Action(action) => {
+                action.target().into_iter().collect()
+            }
+            CombatPhase::Reward(winner, _) => vec![*winner],
+            CombatPhase::Drafting | CombatPhase::Roll | CombatPhase::EncounterOver => vec![],
+        };
+        if combat_state.current_character >= in_battle
+            || phase_positions.iter().any(|n| *n >= in_battle)
+        {
+            return Err("save refers to a position outside of the battle".to_owned());
+        }
+        Ok(())
+    }
+}
+
+impl SavedCombatant {
+    /// Every die the combatant has, wherever it is.
+    fn dice(&self) -> impl Iterator<Item = &Die> {
+        let pool = self.dice_pool.iter().flat_map(|pool| {
+            pool.available
+                .iter()
+                .chain(pool.drafted.iter())
+                .chain(pool.rolled.iter())
+        });
+        let prepped_attack = self
+            .heavy_attacker
+            .iter()
+            .flat_map(|heavy_attacker| heavy_attacker.prepped_attack.iter());
+        let prepped_defense = self
+            .defender
+            .iter()
+            .flat_map(|defender| defender.prepped_defense.iter());
+        pool.chain(prepped_attack).chain(prepped_defense)
+    }
+}
+
+fn restore_combatant(world: &mut World, saved: SavedCombatant) -> Entity {
+    let mut builder = world.create_entity();
+    if let Some(named) = saved.named {
+        builder = builder.with(named);
+    }
+    if let Some(health) = saved.health {
+        builder = builder.with(health);
+    }
+    if let Some(dice_pool) = saved.dice_pool {
+        builder = builder.with(dice_pool);
+    }
+    if saved.light_attacker {
+        builder = builder.with(LightAttacker);
+    }
+    if let Some(heavy_attacker) = saved.heavy_attacker {
+        builder = builder.with(heavy_attacker);
+    }
+    if let Some(defender) = saved.defender {
+        builder = builder.with(defender);
+    }
+    if saved.enemy {
+        builder = builder.with(Enemy);
+    }
+    builder.build()
+}
+
+/// Save a room's world to `<dir>/<code>.json`.
+pub fn save_room(dir: &str, code: &str, world: &World) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let data = serde_json::to_string(&SaveFile::from_world(world))?;
+    // write to a temporary file first, so a crash mid-write can't corrupt the previous save
+    let path = Path::new(dir).join(format!("{}.json", code));
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, data)?;
+    fs::rename(tmp_path, path)
+}
+
+/// Delete the save of a room, if it has one.
+pub fn delete_room(dir: &str, code: &str) -> io::Result<()> {
+    match fs::remove_file(Path::new(dir).join(format!("{}.json", code))) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+/// Load every saved room from the directory, skipping (and reporting) any that can't be restored.
+pub fn load_rooms(dir: &str) -> Vec<(String, SaveFile)> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    let mut rooms = vec![];
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        let code = path.file_stem().unwrap().to_string_lossy().into_owned();
+        match read_save(&path) {
+            Ok(save) => rooms.push((code, save)),
+            Err(err) => error!(room = %code, %err, "Could not load saved room"),
+        }
+    }
+    rooms
+}
+
+fn read_save(path: &Path) -> Result<SaveFile, String> {
+    let data = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    // check the version before parsing the rest, since older formats won't parse
+    let value: serde_json::Value = serde_json::from_str(&data).map_err(|err| err.to_string())?;
+    match value.get("version").and_then(|version| version.as_u64()) {
+        Some(version) if version == u64::from(SAVE_VERSION) => {
+            let save: SaveFile = serde_json::from_value(value).map_err(|err| err.to_string())?;
+            save.validate()?;
+            Ok(save)
+        }
+        version => Err(format!(
+            "save version {:?} but expected {}",
+            version, SAVE_VERSION
+        )),
+    }
+}
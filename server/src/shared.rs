@@ -9,12 +9,46 @@ use std::fmt::Formatter;
 #[derive(Clone, Serialize, Deserialize)]
 pub enum ServerMessage {
     NewState(ClientGameState),
+    Error(String), // the client's last message was rejected
+    Matchmaking(MatchmakingStatus),
+    Profile {
+        token: String,
+        profile: PlayerProfile,
+    }, // sent on connect, and whenever the profile changes
+}
+
+/// Persistent profile of a guest player, who is identified by the token the server gave them.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct PlayerProfile {
+    pub id: String,
+    pub name: String,
+    pub unlocked_dice: Vec<Die>, // every new die the player has won in a campaign run
+    pub rating: u32,
+    pub wins: usize,
+    pub losses: usize,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub enum MatchmakingStatus {
+    Searching,
+    Found { room: String, seat: usize }, // join this room with the given seat to play the match
+    Cancelled,
+    TimedOut, // nobody else was looking for a match
 }
 
 /// Message from the client to the server.
 #[derive(Clone, Serialize, Deserialize)]
 pub enum ClientMessage {
     FinishDrafting(Vec<usize>),
+    ChooseReward(usize, Option<usize>), // chosen reward, and the die in the pool it applies to (if any)
+    ChooseNode(usize),                  // chosen node on the current floor of the campaign map
+    StartRun,                           // start a new campaign run once the last one is over
+    ChooseAction(usize),                // chosen action from SelectAction
+    ChooseTarget(usize),                // chosen target, by its position in the combatants
+    CancelAction,                       // go back from SelectTarget to choosing an action
+    FindMatch,                          // look for a PvP opponent with a similar rating
+    CancelFindMatch,
+    SetName(String), // change the player's display name
 }
 
 // Representation of game state on client side
@@ -23,7 +57,11 @@ pub enum ClientMessage {
 pub struct ClientGameState {
     pub client_phase: ClientPhase,
     pub combatants: Vec<ClientCombatant>,
-    pub combat_log: Vec<String>,
+    pub current_combatant: Option<usize>, // whose turn it is during a battle, by position in combatants
+    pub turn_time_left: Option<u64>, // seconds left for the current phase of the turn, if the room has a turn timer
+    pub spectating: bool,            // spectators get the same state as players, but can't act
+    pub seat: Option<usize>,         // the viewer's seat in a PvP match
+    pub combat_log: Vec<LogEntry>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -31,30 +69,198 @@ pub enum ClientPhase {
     Waiting,
     DraftDice(Vec<Die>, usize), // server gives us available dice to pick from
     SelectAction(Vec<Die>, Vec<ClientAction>), // server gives us rolled dice and available actions to pick from
-    // TODO: target probably needs to be a (String, Uuid) or something, unless we can easily translate Name -> Entity on server side
-    SelectTarget(Vec<String>), // server gives us available targets to pick from
+    SelectTarget(Vec<Die>, ClientAction, Vec<usize>), // server gives us rolled dice, the chosen action, and the positions of targets in the combatants
+    ChooseReward(Vec<Reward>, Vec<Die>), // server gives us rewards to pick from, and the dice pool they can apply to
+    SelectNode(usize, usize, Vec<MapNode>), // server gives us the current floor, total floors, and nodes to pick from
+    RunSummary(RunSummary),                 // the campaign run is over
+    MatchResult(MatchResult),               // the PvP match is over
 }
 
+/// Dice the viewer isn't allowed to see (e.g. an opponent's pool) are sent as `Die::hidden`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClientCombatant {
     pub name: String,
     pub hp: usize,
-    // TODO: attack and defend dice
+    pub max_hp: usize,
+    pub side: usize, // 0 for the party (or the first seat's team), 1 for enemies (or the second seat's team)
+    pub pool: Vec<Die>, // dice available to draft, hidden from opponents
+    pub prepped_attack: Vec<Die>, // dice prepped for a heavy attack
+    pub prepped_defense: Vec<Die>, // dice prepped for defense, which block damage until their next turn
+}
+
+/// Entry in the combat log. Combatants are referred to by name, and the client decides how to display each entry.
+/// Entries that can hold hidden dice also have the combatant's side (as in `ClientCombatant`), which decides who
+/// gets to see them.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub enum LogEntry {
+    EnteredNode(String),
+    TurnStarted {
+        round: usize,
+        combatant: String,
+    },
+    Drafted {
+        combatant: String,
+        side: usize,
+        die: Die,
+    },
+    Rolled {
+        combatant: String,
+        side: usize,
+        dice: Vec<Die>,
+    },
+    Exploded {
+        combatant: String,
+        side: usize,
+        die: Die,
+        face: Face,
+    },
+    CriticalHit {
+        combatant: String,
+        die: Die,
+        damage: usize,
+    },
+    Attack {
+        attacker: String,
+        target: String,
+        heavy: bool,
+        damage: usize,
+        blocked: usize,
+    },
+    Prepped {
+        combatant: String,
+        side: usize,
+        prep: Prep,
+        dice: Vec<Die>,
+    },
+    TimedOut {
+        combatant: String,
+    }, // ran out of time, so the rest of the turn was played automatically
+    DieBroke {
+        combatant: String,
+        die: Die,
+    }, // a fragile die was used up
+    Death {
+        combatant: String,
+    },
+    BattleWon {
+        combatant: String,
+    },
+    PartyDefeated,
+    MatchWon {
+        seat: usize,
+    },
+    BattleEnded, // stopped early by an admin
+    Reward {
+        combatant: String,
+        reward: Reward,
+        die: Die, // the die after the reward was applied to it, or the removed die
+    },
+    // no StatusTick yet: there are no status effects to tick, so it comes with the first one
+}
+
+#[derive(Copy, Clone, Serialize, Deserialize, Debug)]
+pub enum Prep {
+    HeavyAttack,
+    Defense,
+}
+
+/// Node on a floor of the campaign map.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct MapNode {
+    pub name: String,
+    pub kind: MapNodeKind,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub enum MapNodeKind {
+    Encounter(Vec<String>), // names of the enemies to fight
+    Rest(usize),            // heal the party by this much
+    Reward,                 // pick a reward without fighting
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct MatchResult {
+    pub encounter: String,
+    pub winner: Option<usize>, // seat of the winning player, if the match wasn't ended early
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct RunSummary {
+    pub campaign: String,
+    pub victory: bool,
+    pub floors_cleared: usize,
+    pub total_floors: usize,
+    pub encounters_won: usize,
+    pub visited: Vec<String>, // names of the nodes visited, in order
+}
+
+/// Reward offered after winning a battle. Rewards other than NewDie apply to a die chosen from the pool.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub enum Reward {
+    NewDie(Die),
+    UpgradeSides(usize), // add this many faces to the die, numbered above its highest face
+    ChangeColor(Color),
+    RemoveDie,
+}
+impl Reward {
+    pub fn needs_die(&self) -> bool {
+        !matches!(self, Reward::NewDie(_))
+    }
+}
+impl Display for Reward {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Reward::NewDie(die) => write!(f, "Add a new {} die", die),
+            Reward::UpgradeSides(n) => write!(f, "Add {} sides to a die", n),
+            Reward::ChangeColor(color) => write!(f, "Change a die's color to {:?}", color),
+            Reward::RemoveDie => write!(f, "Remove a die"),
+        }
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub enum ClientAction {
     LightAttack,
     PrepHeavyAttack,
-    HeavyAttack,
+    HeavyAttack(Vec<Die>), // dice prepped on an earlier turn, which add to the rolled dice
     Defend,
 }
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+/// Damage dealt by an attack with the given rolled dice, before the target blocks any of it.
+/// Critical dice double their max face, and every crit face doubles the total.
+pub fn attack_damage(dice: &[Die]) -> usize {
+    let mut damage = 0;
+    let mut crits = 0;
+    for die in dice {
+        for face in die.rolled_faces() {
+            if die.is_critical_hit(face) {
+                damage += face.attack_value() * 2;
+            } else {
+                damage += face.attack_value();
+            }
+            if face == Face::Crit {
+                crits += 1;
+            }
+        }
+    }
+    damage * 2usize.pow(crits)
+}
+
+/// Damage blocked by prepped defense dice.
+pub fn defense_block(dice: &[Die]) -> usize {
+    dice.iter()
+        .flat_map(|die| die.rolled_faces())
+        .map(|face| face.defense_value())
+        .sum()
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Die {
     pub color: Color,
-    pub sides: usize,
-    pub rolled_value: Option<usize>,
+    pub faces: Vec<Face>,
+    pub rules: DieRules,
+    pub rolled_value: Option<Face>,
+    pub exploded: Vec<Face>, // extra faces rolled when an exploding die lands on its max face
 }
 impl Display for Die {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
@@ -66,49 +272,177 @@ impl Display for Die {
             Color::Colorless => "non",
         };
         write!(f, "{}", color)?;
-        if let Some(rolled) = self.rolled_value {
-            write!(f, "{} ({})", rolled, self.sides)?;
+        if self.is_hidden() {
+            write!(f, "?")?;
+        } else if let Some(rolled) = self.rolled_value {
+            write!(f, "{}", rolled)?;
+            for face in self.exploded.iter() {
+                write!(f, "+{}", face)?;
+            }
+            write!(f, " ({})", self.sides())?;
+        } else if self.is_standard() {
+            write!(f, "{}", self.sides())?;
         } else {
-            write!(f, "{}", self.sides)?;
+            write!(
+                f,
+                "[{}]",
+                self.faces
+                    .iter()
+                    .map(|face| face.to_string())
+                    .collect::<Vec<String>>()
+                    .join(",")
+            )?;
         }
         Ok(())
     }
 }
 
 impl Die {
-    pub fn colorless(n: usize) -> Self {
+    /// Standard die with faces numbered 1 to n.
+    pub fn standard(color: Color, n: usize) -> Self {
+        Die::custom(color, (1..=n).map(Face::Number).collect())
+    }
+    /// Die with an arbitrary list of faces, e.g. `[0, 0, 2, 2, 4, 6]` or symbol faces.
+    /// Panics without any faces, since the die could never be rolled.
+    pub fn custom(color: Color, faces: Vec<Face>) -> Self {
+        assert!(!faces.is_empty(), "a die needs at least one face");
         Die {
-            color: Color::Colorless,
-            sides: n,
+            color,
+            faces,
+            rules: DieRules::default(),
             rolled_value: None,
+            exploded: vec![],
         }
     }
-    pub fn blue(n: usize) -> Self {
+    /// Die that the viewer isn't allowed to see, other than its color.
+    /// It has no faces, which no real die can have.
+    pub fn hidden(color: Color) -> Self {
         Die {
-            color: Color::Blue,
-            sides: n,
+            color,
+            faces: vec![],
+            rules: DieRules::default(),
             rolled_value: None,
+            exploded: vec![],
         }
     }
+    pub fn colorless(n: usize) -> Self {
+        Die::standard(Color::Colorless, n)
+    }
+    pub fn blue(n: usize) -> Self {
+        Die::standard(Color::Blue, n)
+    }
     pub fn red(n: usize) -> Self {
-        Die {
-            color: Color::Red,
-            sides: n,
-            rolled_value: None,
-        }
+        Die::standard(Color::Red, n)
     }
     pub fn yellow(n: usize) -> Self {
-        Die {
-            color: Color::Yellow,
-            sides: n,
-            rolled_value: None,
-        }
+        Die::standard(Color::Yellow, n)
     }
     pub fn green(n: usize) -> Self {
-        Die {
-            color: Color::Green,
-            sides: n,
-            rolled_value: None,
+        Die::standard(Color::Green, n)
+    }
+
+    pub fn exploding(mut self) -> Self {
+        self.rules.exploding = true;
+        self
+    }
+    pub fn critical(mut self) -> Self {
+        self.rules.critical = true;
+        self
+    }
+    pub fn fragile(mut self) -> Self {
+        self.rules.fragile = true;
+        self
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.faces.is_empty()
+    }
+
+    pub fn sides(&self) -> usize {
+        self.faces.len()
+    }
+
+    /// True if this die is a plain d`n` with faces numbered 1 to n.
+    pub fn is_standard(&self) -> bool {
+        self.faces
+            .iter()
+            .enumerate()
+            .all(|(i, face)| *face == Face::Number(i + 1))
+    }
+
+    /// The highest numbered face, which triggers the exploding and critical rules.
+    pub fn max_face(&self) -> Option<Face> {
+        self.faces
+            .iter()
+            .filter_map(|face| match face {
+                Face::Number(n) => Some(*n),
+                _ => None,
+            })
+            .max()
+            .map(Face::Number)
+    }
+
+    /// All faces this die landed on, including any extra rolls from exploding.
+    pub fn rolled_faces(&self) -> impl Iterator<Item = Face> + '_ {
+        self.rolled_value
+            .iter()
+            .chain(self.exploded.iter())
+            .copied()
+    }
+
+    /// True if the die is critical and the given rolled face is its max face, doubling the face's damage.
+    pub fn is_critical_hit(&self, face: Face) -> bool {
+        self.rules.critical && Some(face) == self.max_face()
+    }
+
+    /// Clear the rolled result so the die can be drafted again.
+    pub fn reset(&mut self) {
+        self.rolled_value = None;
+        self.exploded.clear();
+    }
+}
+
+// Stops a die (e.g. a d1) from exploding forever
+pub const MAX_EXPLOSIONS: usize = 5;
+
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DieRules {
+    pub exploding: bool, // rolling the max face rolls again and adds the result
+    pub critical: bool,  // rolling the max face doubles its damage
+    pub fragile: bool,   // the die is removed from the pool after being used
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Face {
+    Number(usize),
+    Shield(usize), // blocks the given amount when used for defense, but does no damage
+    Crit,          // doubles the damage of the attack it is part of
+    Blank,
+}
+impl Display for Face {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Face::Number(n) => write!(f, "{}", n),
+            Face::Shield(n) => write!(f, "shield{}", n),
+            Face::Crit => write!(f, "crit"),
+            Face::Blank => write!(f, "blank"),
+        }
+    }
+}
+
+impl Face {
+    /// How much this face adds to the damage of an attack.
+    pub fn attack_value(&self) -> usize {
+        match self {
+            Face::Number(n) => *n,
+            _ => 0,
+        }
+    }
+    /// How much this face blocks when prepped for defense.
+    pub fn defense_value(&self) -> usize {
+        match self {
+            Face::Number(n) | Face::Shield(n) => *n,
+            _ => 0,
         }
     }
 }
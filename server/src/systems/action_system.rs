@@ -1,37 +1,79 @@
 use crate::combat_state::CombatAction;
 use crate::combat_state::CombatPhase;
 use crate::combat_state::CombatState;
+use crate::combat_state::Transition;
 use crate::components::Defender;
 use crate::components::DicePool;
+use crate::components::Enemy;
 use crate::components::Health;
 use crate::components::HeavyAttacker;
 use crate::components::LightAttacker;
 use crate::components::Named;
+use crate::events::CancelAction;
+use crate::events::ChooseAction;
+use crate::events::ChooseTarget;
+use crate::events::CombatantDied;
+use crate::events::DamageDealt;
+use crate::events::TurnEnded;
+use crate::events::TurnStarted;
 use crate::log::CombatLog;
+use crate::shared::attack_damage;
+use crate::shared::defense_block;
+use crate::shared::Die;
+use crate::shared::LogEntry;
+use crate::shared::Prep;
+use specs::shrev::EventChannel;
+use specs::Entity;
+use specs::Read;
 use specs::ReadStorage;
+use specs::ReaderId;
+use specs::SystemData;
+use specs::World;
+use specs::Write;
 use specs::WriteExpect;
 use specs::WriteStorage;
 
 use specs::System;
 
-pub struct ActionSystem;
+#[derive(Default)]
+pub struct ActionSystem {
+    choose_action_reader: Option<ReaderId<ChooseAction>>,
+    choose_target_reader: Option<ReaderId<ChooseTarget>>,
+    cancel_action_reader: Option<ReaderId<CancelAction>>,
+}
 
 impl<'a> System<'a> for ActionSystem {
     #[allow(clippy::type_complexity)]
     type SystemData = (
+        Read<'a, EventChannel<ChooseAction>>,
+        Read<'a, EventChannel<ChooseTarget>>,
+        Read<'a, EventChannel<CancelAction>>,
+        Write<'a, EventChannel<DamageDealt>>,
+        Write<'a, EventChannel<CombatantDied>>,
+        Write<'a, EventChannel<TurnStarted>>,
+        Write<'a, EventChannel<TurnEnded>>,
         ReadStorage<'a, Named>,
+        ReadStorage<'a, Enemy>,
         ReadStorage<'a, LightAttacker>,
         WriteStorage<'a, HeavyAttacker>,
         WriteStorage<'a, Defender>,
         WriteStorage<'a, Health>,
         WriteStorage<'a, DicePool>,
         WriteExpect<'a, CombatState>,
-        WriteExpect<'a, CombatLog>,
+        Write<'a, CombatLog>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
         let (
+            choose_action_events,
+            choose_target_events,
+            cancel_action_events,
+            mut damage_dealt_events,
+            mut combatant_died_events,
+            mut turn_started_events,
+            mut turn_ended_events,
             names,
+            enemies,
             light_attackers,
             mut heavy_attackers,
             mut defenders,
@@ -42,7 +84,33 @@ impl<'a> System<'a> for ActionSystem {
         ) = data;
         let current_entity = combat_state.combatants[combat_state.current_character];
 
-        // SelectAction phase: populate possible actions if they are not already populated
+        // Choosing an action moves on to the Action phase, or to SelectTarget first for targeted actions
+        for ChooseAction(n) in
+            choose_action_events.read(self.choose_action_reader.as_mut().unwrap())
+        {
+            combat_state.transition(Transition::ChooseAction(*n));
+        }
+        for ChooseTarget(target) in
+            choose_target_events.read(self.choose_target_reader.as_mut().unwrap())
+        {
+            let is_target = *target != combat_state.current_character
+                && combat_state
+                    .combatants
+                    .get(*target)
+                    .is_some_and(|combatant| {
+                        healths.get(*combatant).is_some_and(|health| health.hp > 0)
+                    });
+            if is_target {
+                combat_state.transition(Transition::ChooseTarget(*target));
+            }
+        }
+        // cancelling goes back to SelectAction, where the possible actions get populated again below
+        for CancelAction in cancel_action_events.read(self.cancel_action_reader.as_mut().unwrap()) {
+            combat_state.transition(Transition::CancelAction);
+        }
+
+        // SelectAction phase: populate possible actions if they are not already populated, which is the
+        // case right after rolling or cancelling an action
         if let CombatPhase::SelectAction(possible_actions) = &combat_state.current_phase {
             if possible_actions.is_empty() {
                 let mut possible_actions = vec![];
@@ -62,30 +130,41 @@ impl<'a> System<'a> for ActionSystem {
                 if defenders.get(current_entity).is_some() {
                     possible_actions.push(("Defend".to_owned(), CombatAction::Defend))
                 }
-                combat_state.current_phase = CombatPhase::SelectAction(possible_actions);
+                if !possible_actions.is_empty() {
+                    combat_state.transition(Transition::ListActions(possible_actions));
+                }
             }
         }
 
-        // Action Phase: resolve the action, but only if a target has been chosen (for targeted actions)
+        // Action Phase: resolve the action, which has its target by now if it needs one
         let mut did_action = false;
         if let CombatPhase::Action(action) = &combat_state.current_phase {
+            let combatants = &combat_state.combatants;
             match action {
                 CombatAction::LightAttack(Some(target)) => {
+                    let target = &combatants[*target];
                     if let Some(health) = healths.get_mut(*target) {
                         if let Some(dice_pool) = dice_pools.get(current_entity) {
-                            let damage = dice_pool
-                                .rolled
-                                .iter()
-                                .map(|die| die.rolled_value.unwrap())
-                                .sum::<usize>();
-                            // TODO: this damage dealing needs to consider defense
-                            health.hp -= damage;
-                            combat_log.add(format!(
-                                "{} light attack did {} damage to {}",
-                                names.get(current_entity).unwrap().name,
-                                damage,
-                                names.get(*target).unwrap().name,
-                            ));
+                            let attacker = &names.get(current_entity).unwrap().name;
+                            log_critical_hits(&dice_pool.rolled, attacker, &mut combat_log);
+                            let damage = attack_damage(&dice_pool.rolled);
+                            let blocked = defenders
+                                .get(*target)
+                                .map_or(0, |defender| defense_block(&defender.prepped_defense))
+                                .min(damage);
+                            let was_alive = health.hp > 0;
+                            health.hp = health.hp.saturating_sub(damage - blocked);
+                            damage_dealt_events.single_write(DamageDealt {
+                                attacker: current_entity,
+                                target: *target,
+                                heavy: false,
+                                damage: damage - blocked,
+                                blocked,
+                            });
+                            if was_alive && health.hp == 0 {
+                                combatant_died_events
+                                    .single_write(CombatantDied { combatant: *target });
+                            }
                         }
                     }
                     did_action = true;
@@ -94,33 +173,47 @@ impl<'a> System<'a> for ActionSystem {
                     if let Some(heavy_attack) = heavy_attackers.get_mut(current_entity) {
                         if let Some(dice_pool) = dice_pools.get_mut(current_entity) {
                             heavy_attack.prepped_attack.append(&mut dice_pool.rolled);
-                            combat_log.add(format!(
-                                "{} prepped for heavy attack",
-                                names.get(current_entity).unwrap().name,
-                            ));
+                            combat_log.add(LogEntry::Prepped {
+                                combatant: names.get(current_entity).unwrap().name.clone(),
+                                side: if enemies.contains(current_entity) {
+                                    1
+                                } else {
+                                    0
+                                },
+                                prep: Prep::HeavyAttack,
+                                dice: heavy_attack.prepped_attack.clone(),
+                            });
                         }
                     }
                     did_action = true;
                 }
                 CombatAction::HeavyAttack(Some(target)) => {
+                    let target = &combatants[*target];
                     if let Some(heavy_attack) = heavy_attackers.get_mut(current_entity) {
                         if let Some(health) = healths.get_mut(*target) {
                             if let Some(dice_pool) = dice_pools.get_mut(current_entity) {
                                 // TODO: here would be something more complicated, to calculate bonus damage, but for now we just sum all dice values
                                 dice_pool.rolled.append(&mut heavy_attack.prepped_attack);
-                                let damage = dice_pool
-                                    .rolled
-                                    .iter()
-                                    .map(|die| die.rolled_value.unwrap())
-                                    .sum::<usize>();
-                                // TODO: this damage dealing needs to consider defense
-                                health.hp -= damage;
-                                combat_log.add(format!(
-                                    "{} heavy attack did {} damage to {}",
-                                    names.get(current_entity).unwrap().name,
-                                    damage,
-                                    names.get(*target).unwrap().name,
-                                ));
+                                let attacker = &names.get(current_entity).unwrap().name;
+                                log_critical_hits(&dice_pool.rolled, attacker, &mut combat_log);
+                                let damage = attack_damage(&dice_pool.rolled);
+                                let blocked = defenders
+                                    .get(*target)
+                                    .map_or(0, |defender| defense_block(&defender.prepped_defense))
+                                    .min(damage);
+                                let was_alive = health.hp > 0;
+                                health.hp = health.hp.saturating_sub(damage - blocked);
+                                damage_dealt_events.single_write(DamageDealt {
+                                    attacker: current_entity,
+                                    target: *target,
+                                    heavy: true,
+                                    damage: damage - blocked,
+                                    blocked,
+                                });
+                                if was_alive && health.hp == 0 {
+                                    combatant_died_events
+                                        .single_write(CombatantDied { combatant: *target });
+                                }
                             }
                         }
                     }
@@ -130,10 +223,16 @@ impl<'a> System<'a> for ActionSystem {
                     if let Some(defender) = defenders.get_mut(current_entity) {
                         if let Some(dice_pool) = dice_pools.get_mut(current_entity) {
                             defender.prepped_defense.append(&mut dice_pool.rolled);
-                            combat_log.add(format!(
-                                "{} prepped for defense",
-                                names.get(current_entity).unwrap().name,
-                            ));
+                            combat_log.add(LogEntry::Prepped {
+                                combatant: names.get(current_entity).unwrap().name.clone(),
+                                side: if enemies.contains(current_entity) {
+                                    1
+                                } else {
+                                    0
+                                },
+                                prep: Prep::Defense,
+                                dice: defender.prepped_defense.clone(),
+                            });
                         }
                     }
                     did_action = true;
@@ -142,11 +241,68 @@ impl<'a> System<'a> for ActionSystem {
             }
         }
         if did_action {
-            // move to next character's turn, starting with Drafting phase
-            combat_state.current_phase = CombatPhase::Drafting;
-            combat_state.current_character += 1;
-            if combat_state.current_character >= combat_state.combatants.len() {
-                combat_state.current_character = 0;
+            // move to next living character's turn, starting with Drafting phase
+            combat_state.transition(Transition::EndTurn);
+            turn_ended_events.single_write(TurnEnded {
+                round: combat_state.round,
+                combatant: current_entity,
+            });
+            for _ in 0..combat_state.combatants.len() {
+                combat_state.current_character += 1;
+                if combat_state.current_character >= combat_state.combatants.len() {
+                    combat_state.current_character = 0;
+                    combat_state.round += 1;
+                }
+                let next_entity = combat_state.combatants[combat_state.current_character];
+                if healths.get(next_entity).is_some_and(|health| health.hp > 0) {
+                    break;
+                }
+            }
+
+            // the battle is over if either side is all dead, and RewardSystem takes it from there
+            let is_alive = |entity| healths.get(entity).is_some_and(|health| health.hp > 0);
+            let (enemy_side, party_side): (Vec<Entity>, Vec<Entity>) = combat_state
+                .combatants
+                .iter()
+                .partition(|combatant| enemies.contains(**combatant));
+            if enemy_side.into_iter().any(is_alive) && party_side.into_iter().any(is_alive) {
+                turn_started_events.single_write(TurnStarted {
+                    round: combat_state.round,
+                    combatant: combat_state.combatants[combat_state.current_character],
+                });
+            }
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.choose_action_reader = Some(
+            world
+                .fetch_mut::<EventChannel<ChooseAction>>()
+                .register_reader(),
+        );
+        self.choose_target_reader = Some(
+            world
+                .fetch_mut::<EventChannel<ChooseTarget>>()
+                .register_reader(),
+        );
+        self.cancel_action_reader = Some(
+            world
+                .fetch_mut::<EventChannel<CancelAction>>()
+                .register_reader(),
+        );
+    }
+}
+
+fn log_critical_hits(dice: &[Die], attacker: &str, combat_log: &mut CombatLog) {
+    for die in dice {
+        for face in die.rolled_faces() {
+            if die.is_critical_hit(face) {
+                combat_log.add(LogEntry::CriticalHit {
+                    combatant: attacker.to_owned(),
+                    die: die.clone(),
+                    damage: face.attack_value() * 2,
+                });
             }
         }
     }
@@ -0,0 +1,100 @@
+use crate::components::Named;
+use crate::events::CombatantDied;
+use crate::events::DamageDealt;
+use crate::events::TurnEnded;
+use crate::events::TurnStarted;
+use crate::log::CombatLog;
+use crate::shared::LogEntry;
+use specs::shrev::EventChannel;
+use specs::Read;
+use specs::ReadStorage;
+use specs::ReaderId;
+use specs::SystemData;
+use specs::World;
+use specs::Write;
+use tracing::debug;
+
+use specs::System;
+
+/// Writes what happened in the battle to the combat log, as other systems send events about it.
+/// Runs right after ActionSystem, so its entries come before anything logged later in the same run.
+/// Turns ending aren't part of the combat log, since the next turn starting says as much, so they're only traced.
+#[derive(Default)]
+pub struct CombatLogSystem {
+    damage_dealt_reader: Option<ReaderId<DamageDealt>>,
+    combatant_died_reader: Option<ReaderId<CombatantDied>>,
+    turn_started_reader: Option<ReaderId<TurnStarted>>,
+    turn_ended_reader: Option<ReaderId<TurnEnded>>,
+}
+
+impl<'a> System<'a> for CombatLogSystem {
+    type SystemData = (
+        Read<'a, EventChannel<DamageDealt>>,
+        Read<'a, EventChannel<CombatantDied>>,
+        Read<'a, EventChannel<TurnStarted>>,
+        Read<'a, EventChannel<TurnEnded>>,
+        ReadStorage<'a, Named>,
+        Write<'a, CombatLog>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            damage_dealt_events,
+            combatant_died_events,
+            turn_started_events,
+            turn_ended_events,
+            names,
+            mut combat_log,
+        ) = data;
+        let name = |entity| names.get(entity).unwrap().name.clone();
+
+        for event in turn_ended_events.read(self.turn_ended_reader.as_mut().unwrap()) {
+            debug!(round = event.round, combatant = %name(event.combatant), "Turn ended");
+        }
+        // an attack is logged before the death it causes
+        for event in damage_dealt_events.read(self.damage_dealt_reader.as_mut().unwrap()) {
+            combat_log.add(LogEntry::Attack {
+                attacker: name(event.attacker),
+                target: name(event.target),
+                heavy: event.heavy,
+                damage: event.damage,
+                blocked: event.blocked,
+            });
+        }
+        for event in combatant_died_events.read(self.combatant_died_reader.as_mut().unwrap()) {
+            combat_log.add(LogEntry::Death {
+                combatant: name(event.combatant),
+            });
+        }
+        for event in turn_started_events.read(self.turn_started_reader.as_mut().unwrap()) {
+            combat_log.add(LogEntry::TurnStarted {
+                round: event.round,
+                combatant: name(event.combatant),
+            });
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.damage_dealt_reader = Some(
+            world
+                .fetch_mut::<EventChannel<DamageDealt>>()
+                .register_reader(),
+        );
+        self.combatant_died_reader = Some(
+            world
+                .fetch_mut::<EventChannel<CombatantDied>>()
+                .register_reader(),
+        );
+        self.turn_started_reader = Some(
+            world
+                .fetch_mut::<EventChannel<TurnStarted>>()
+                .register_reader(),
+        );
+        self.turn_ended_reader = Some(
+            world
+                .fetch_mut::<EventChannel<TurnEnded>>()
+                .register_reader(),
+        );
+    }
+}
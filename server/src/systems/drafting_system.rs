@@ -1,71 +1,107 @@
 use crate::combat_state::CombatPhase;
 use crate::combat_state::CombatState;
-use crate::components::Defender;
+use crate::combat_state::Transition;
 use crate::components::DicePool;
+use crate::components::Enemy;
 use crate::components::Named;
-use crate::events::Event;
+use crate::events::DraftDie;
+use crate::events::FinishDrafting;
 use crate::log::CombatLog;
-use crate::EventQueue;
-use specs::ReadExpect;
+use crate::shared::LogEntry;
+use specs::shrev::EventChannel;
+use specs::Read;
 use specs::ReadStorage;
+use specs::ReaderId;
+use specs::SystemData;
+use specs::World;
+use specs::Write;
 use specs::WriteExpect;
 use specs::WriteStorage;
 
 use specs::System;
 
-pub struct DraftingSystem;
+#[derive(Default)]
+pub struct DraftingSystem {
+    draft_die_reader: Option<ReaderId<DraftDie>>,
+    finish_drafting_reader: Option<ReaderId<FinishDrafting>>,
+}
 
 impl<'a> System<'a> for DraftingSystem {
     #[allow(clippy::type_complexity)]
     type SystemData = (
-        ReadExpect<'a, EventQueue>,
+        Read<'a, EventChannel<DraftDie>>,
+        Read<'a, EventChannel<FinishDrafting>>,
         ReadStorage<'a, Named>,
-        WriteStorage<'a, Defender>,
+        ReadStorage<'a, Enemy>,
         WriteStorage<'a, DicePool>,
-        ReadExpect<'a, CombatState>,
-        WriteExpect<'a, CombatLog>,
+        WriteExpect<'a, CombatState>,
+        Write<'a, CombatLog>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (event_queue, names, mut defenders, mut dice_pools, combat_state, mut combat_log) =
-            data;
+        let (
+            draft_die_events,
+            finish_drafting_events,
+            names,
+            enemies,
+            mut dice_pools,
+            mut combat_state,
+            mut combat_log,
+        ) = data;
         let current_entity = combat_state.combatants[combat_state.current_character];
 
-        // At start of Drafting phase, clean up leftover state from last turn by moving all rolled dice back to available dice.
-        if let CombatPhase::Drafting = combat_state.current_phase {
-            if let Some(dice_pool) = dice_pools.get_mut(current_entity) {
-                if !dice_pool.rolled.is_empty() {
-                    for die in dice_pool.rolled.iter_mut() {
-                        die.rolled_value = None;
-                    }
-                    dice_pool.available.append(&mut dice_pool.rolled);
-                }
-                if let Some(defender) = defenders.get_mut(current_entity) {
-                    if !defender.prepped_defense.is_empty() {
-                        for die in defender.prepped_defense.iter_mut() {
-                            die.rolled_value = None;
-                        }
-                        dice_pool.available.append(&mut defender.prepped_defense);
-                    }
-                }
-            }
+        // read the events even when they're ignored, so they don't get drafted in some later turn
+        let draft_choices: Vec<DraftDie> = draft_die_events
+            .read(self.draft_die_reader.as_mut().unwrap())
+            .copied()
+            .collect();
+        let finished = finish_drafting_events
+            .read(self.finish_drafting_reader.as_mut().unwrap())
+            .count()
+            > 0;
+
+        // Nothing to draft outside of the Drafting phase. The dice used last turn are already back in the
+        // pool, since TurnSystem took care of them when the turn started.
+        if !matches!(combat_state.current_phase, CombatPhase::Drafting) {
+            return;
         }
 
-        for event in event_queue.events.iter() {
-            // TODO: this is warning only because we have just one event. in the future we should have more events, so going to leave this as is for now.
-            if let Event::DraftDie(n) = event {
-                if let Some(dice_pool) = dice_pools.get_mut(current_entity) {
-                    if dice_pool.drafted.len() < dice_pool.max_draft_amount {
-                        let die = dice_pool.available.remove(*n);
-                        dice_pool.drafted.push(die);
-                        combat_log.add(format!(
-                            "{} drafted {}",
-                            names.get(current_entity).unwrap().name,
-                            die
-                        ));
-                    }
+        for DraftDie(n) in draft_choices {
+            if let Some(dice_pool) = dice_pools.get_mut(current_entity) {
+                if dice_pool.drafted.len() < dice_pool.max_draft_amount
+                    && n < dice_pool.available.len()
+                {
+                    let die = dice_pool.available.remove(n);
+                    combat_log.add(LogEntry::Drafted {
+                        combatant: names.get(current_entity).unwrap().name.clone(),
+                        side: if enemies.contains(current_entity) {
+                            1
+                        } else {
+                            0
+                        },
+                        die: die.clone(),
+                    });
+                    dice_pool.drafted.push(die);
                 }
             }
         }
+        // the drafted dice are sent along with this, so they've all been drafted by now
+        if finished {
+            combat_state.transition(Transition::FinishDrafting);
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.draft_die_reader = Some(
+            world
+                .fetch_mut::<EventChannel<DraftDie>>()
+                .register_reader(),
+        );
+        self.finish_drafting_reader = Some(
+            world
+                .fetch_mut::<EventChannel<FinishDrafting>>()
+                .register_reader(),
+        );
     }
 }
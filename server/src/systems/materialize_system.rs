@@ -1,12 +1,22 @@
+use crate::campaign::Run;
+use crate::campaign::RunPhase;
+use crate::combat_state::CombatAction;
 use crate::combat_state::CombatPhase;
 use crate::combat_state::CombatState;
+use crate::components::Defender;
 use crate::components::DicePool;
+use crate::components::Enemy;
 use crate::components::Health;
+use crate::components::HeavyAttacker;
 use crate::components::Named;
 use crate::log::CombatLog;
+use crate::room::RoomSettings;
+use crate::shared::ClientAction;
 use crate::shared::ClientCombatant;
 use crate::shared::ClientGameState;
 use crate::shared::ClientPhase;
+use crate::shared::MatchResult;
+use specs::Read;
 use specs::ReadExpect;
 use specs::ReadStorage;
 
@@ -21,23 +31,94 @@ impl<'a> System<'a> for MaterializeSystem {
         ReadStorage<'a, Named>,
         ReadStorage<'a, Health>,
         ReadStorage<'a, DicePool>,
-        ReadExpect<'a, CombatLog>,
+        ReadStorage<'a, HeavyAttacker>,
+        ReadStorage<'a, Defender>,
+        ReadStorage<'a, Enemy>,
+        Read<'a, CombatLog>,
+        ReadExpect<'a, Run>,
+        Read<'a, RoomSettings>,
         WriteExpect<'a, CombatState>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (names, healths, dice_pools, combat_log, mut combat_state) = data;
+        let (
+            names,
+            healths,
+            dice_pools,
+            heavy_attackers,
+            defenders,
+            enemies,
+            combat_log,
+            run,
+            settings,
+            mut combat_state,
+        ) = data;
 
         let current_entity = combat_state.combatants[combat_state.current_character];
 
-        let client_phase = match &combat_state.current_phase {
-            CombatPhase::Drafting => {
+        let client_phase = match (run.phase, &combat_state.current_phase) {
+            (RunPhase::SelectNode, _) => ClientPhase::SelectNode(
+                run.floor,
+                run.campaign.floors.len(),
+                run.current_nodes().to_vec(),
+            ),
+            // a PvP match is a run with a single battle, which the first seat wins if the party survives
+            (RunPhase::Finished(victory), _) if settings.pvp => {
+                ClientPhase::MatchResult(MatchResult {
+                    encounter: run.campaign.name.clone(),
+                    winner: Some(if victory { 0 } else { 1 }),
+                })
+            }
+            (RunPhase::Finished(victory), _) => ClientPhase::RunSummary(run.summary(victory)),
+            (RunPhase::Ended, _) if settings.pvp => ClientPhase::MatchResult(MatchResult {
+                encounter: run.campaign.name.clone(),
+                winner: None,
+            }),
+            (RunPhase::Ended, _) => ClientPhase::RunSummary(run.summary(false)),
+            (_, CombatPhase::Drafting) => {
                 let dice_pool = dice_pools.get(current_entity).unwrap();
                 ClientPhase::DraftDice(dice_pool.available.clone(), dice_pool.max_draft_amount)
             }
+            (_, CombatPhase::Reward(winner, rewards)) => {
+                let dice_pool = dice_pools.get(combat_state.combatants[*winner]).unwrap();
+                ClientPhase::ChooseReward(rewards.clone(), dice_pool.available.clone())
+            }
+            (_, CombatPhase::SelectAction(possible_actions)) if !possible_actions.is_empty() => {
+                let dice_pool = dice_pools.get(current_entity).unwrap();
+                ClientPhase::SelectAction(
+                    dice_pool.rolled.clone(),
+                    possible_actions
+                        .iter()
+                        .map(|(_, action)| {
+                            client_action(action, heavy_attackers.get(current_entity))
+                        })
+                        .collect(),
+                )
+            }
+            (_, CombatPhase::SelectTarget(action)) => {
+                let dice_pool = dice_pools.get(current_entity).unwrap();
+                // any living combatant other than the attacker can be targeted
+                let targets = (0..combat_state.combatants.len())
+                    .filter(|n| {
+                        *n != combat_state.current_character
+                            && healths
+                                .get(combat_state.combatants[*n])
+                                .is_some_and(|health| health.hp > 0)
+                    })
+                    .collect();
+                ClientPhase::SelectTarget(
+                    dice_pool.rolled.clone(),
+                    client_action(action, heavy_attackers.get(current_entity)),
+                    targets,
+                )
+            }
             // TODO: implement other phases
             _ => ClientPhase::Waiting,
         };
+        let in_battle = match combat_state.current_phase {
+            CombatPhase::Reward(_, _) | CombatPhase::EncounterOver => false,
+            _ => run.phase == RunPhase::InNode,
+        };
 
         combat_state.materialized_state = ClientGameState {
             client_phase,
@@ -51,13 +132,48 @@ impl<'a> System<'a> for MaterializeSystem {
                         Some(ClientCombatant {
                             name: named.name.clone(),
                             hp: health.hp,
+                            max_hp: health.max_hp,
+                            side: if enemies.contains(*combatant) { 1 } else { 0 },
+                            pool: dice_pools
+                                .get(*combatant)
+                                .map_or(vec![], |dice_pool| dice_pool.available.clone()),
+                            prepped_attack: heavy_attackers
+                                .get(*combatant)
+                                .map_or(vec![], |heavy_attacker| {
+                                    heavy_attacker.prepped_attack.clone()
+                                }),
+                            prepped_defense: defenders
+                                .get(*combatant)
+                                .map_or(vec![], |defender| defender.prepped_defense.clone()),
                         })
                     } else {
                         None
                     }
                 })
                 .collect(),
-            combat_log: combat_log.logs.clone(),
+            current_combatant: if in_battle {
+                Some(combat_state.current_character)
+            } else {
+                None
+            },
+            // filled in by the room when sending
+            turn_time_left: None,
+            spectating: false,
+            seat: None,
+            combat_log: combat_log.entries.clone(),
         };
     }
 }
+
+fn client_action(action: &CombatAction, heavy_attacker: Option<&HeavyAttacker>) -> ClientAction {
+    match action {
+        CombatAction::LightAttack(_) => ClientAction::LightAttack,
+        CombatAction::PrepHeavyAttack => ClientAction::PrepHeavyAttack,
+        CombatAction::HeavyAttack(_) => {
+            ClientAction::HeavyAttack(heavy_attacker.map_or(vec![], |heavy_attacker| {
+                heavy_attacker.prepped_attack.clone()
+            }))
+        }
+        CombatAction::Defend => ClientAction::Defend,
+    }
+}
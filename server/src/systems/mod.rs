@@ -1,11 +1,49 @@
 mod action_system;
+mod combat_log_system;
 mod drafting_system;
 mod materialize_system;
+mod reward_system;
 mod rolling_system;
+mod turn_system;
 // mod ui_system;
 
 pub use action_system::ActionSystem;
+pub use combat_log_system::CombatLogSystem;
 pub use drafting_system::DraftingSystem;
 pub use materialize_system::MaterializeSystem;
+pub use reward_system::{generate_rewards, RewardSystem};
 pub use rolling_system::RollingSystem;
+pub use turn_system::TurnSystem;
 // pub use ui_system::UiSystem;
+
+use specs::DispatcherBuilder;
+
+// names of the systems, for other systems to say which ones they run after
+pub const DRAFTING: &str = "drafting";
+pub const ROLLING: &str = "rolling";
+pub const ACTION: &str = "action";
+pub const COMBAT_LOG: &str = "combat_log";
+pub const TURN: &str = "turn";
+pub const REWARD: &str = "reward";
+
+/// The systems each room runs once for every message, with what each one needs to run after. Each system
+/// runs after the ones sending the events it reads, and the phases it moves the battle into are handled by
+/// the systems after it, so a single run takes the battle as far as it goes without another message
+/// (see `Room::update`). Setting up the dispatcher this builds registers every component and resource
+/// (with a `Default`) the systems use, along with their event readers.
+///
+/// New systems (AI, status effects, timers...) only need to be added here, e.g.
+/// `.with(AiSystem::default(), "ai", &[ACTION])` for one reacting to the events ActionSystem sends.
+pub fn game_systems<'a, 'b>() -> DispatcherBuilder<'a, 'b> {
+    DispatcherBuilder::new()
+        .with(DraftingSystem::default(), DRAFTING, &[])
+        // rolls the dice that were just drafted
+        .with(RollingSystem {}, ROLLING, &[DRAFTING])
+        // acts with the dice that were just rolled
+        .with(ActionSystem::default(), ACTION, &[ROLLING])
+        .with(CombatLogSystem::default(), COMBAT_LOG, &[ACTION])
+        // gets the combatant whose turn just started ready for it, logging after the turn starting
+        .with(TurnSystem::default(), TURN, &[ACTION, COMBAT_LOG])
+        // the battle's end is logged after whatever ended it
+        .with(RewardSystem::default(), REWARD, &[ACTION, COMBAT_LOG, TURN])
+}
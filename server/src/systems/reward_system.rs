@@ -0,0 +1,216 @@
+use crate::combat_state::CombatPhase;
+use crate::combat_state::CombatState;
+use crate::combat_state::Transition;
+use crate::components::Defender;
+use crate::components::DicePool;
+use crate::components::Enemy;
+use crate::components::Health;
+use crate::components::HeavyAttacker;
+use crate::components::Named;
+use crate::events::ChooseReward;
+use crate::log::CombatLog;
+use crate::rng::DiceRng;
+use crate::room::RoomSettings;
+use crate::shared::Color;
+use crate::shared::Die;
+use crate::shared::Face;
+use crate::shared::LogEntry;
+use crate::shared::Reward;
+use specs::shrev::EventChannel;
+use specs::Read;
+use specs::ReadStorage;
+use specs::ReaderId;
+use specs::SystemData;
+use specs::World;
+use specs::Write;
+use specs::WriteExpect;
+use specs::WriteStorage;
+
+use specs::System;
+
+const REWARD_COLORS: [Color; 4] = [Color::Blue, Color::Red, Color::Yellow, Color::Green];
+const REWARD_SIDES: [usize; 3] = [4, 6, 8];
+
+#[derive(Default)]
+pub struct RewardSystem {
+    choose_reward_reader: Option<ReaderId<ChooseReward>>,
+}
+
+impl<'a> System<'a> for RewardSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        Read<'a, EventChannel<ChooseReward>>,
+        ReadStorage<'a, Named>,
+        ReadStorage<'a, Enemy>,
+        ReadStorage<'a, Health>,
+        WriteStorage<'a, HeavyAttacker>,
+        WriteStorage<'a, Defender>,
+        WriteStorage<'a, DicePool>,
+        WriteExpect<'a, CombatState>,
+        Write<'a, CombatLog>,
+        WriteExpect<'a, DiceRng>,
+        Read<'a, RoomSettings>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            choose_reward_events,
+            names,
+            enemies,
+            healths,
+            mut heavy_attackers,
+            mut defenders,
+            mut dice_pools,
+            mut combat_state,
+            mut combat_log,
+            mut rng,
+            settings,
+        ) = data;
+        // read the choices even when they're ignored, so they don't get applied to some later reward
+        let reward_choices: Vec<ChooseReward> = choose_reward_events
+            .read(self.choose_reward_reader.as_mut().unwrap())
+            .copied()
+            .collect();
+
+        // Once every enemy is dead, the battle is won. Party members get their dice back, and the first one still standing picks a reward.
+        // If the whole party is dead instead, the battle is lost and ends without a reward.
+        match combat_state.current_phase {
+            CombatPhase::Reward(_, _) | CombatPhase::EncounterOver => {}
+            _ => {
+                let is_alive = |entity| healths.get(entity).is_some_and(|health| health.hp > 0);
+                let enemies_dead = combat_state
+                    .combatants
+                    .iter()
+                    .filter(|combatant| enemies.contains(**combatant))
+                    .all(|enemy| !is_alive(*enemy));
+                let survivors: Vec<usize> = (0..combat_state.combatants.len())
+                    .filter(|n| {
+                        let combatant = combat_state.combatants[*n];
+                        !enemies.contains(combatant) && is_alive(combatant)
+                    })
+                    .collect();
+                if settings.pvp && (survivors.is_empty() || enemies_dead) {
+                    // no rewards in a PvP match, it's over as soon as either team is dead
+                    combat_log.add(LogEntry::MatchWon {
+                        seat: if survivors.is_empty() { 1 } else { 0 },
+                    });
+                    combat_state.transition(Transition::EndEncounter);
+                } else if survivors.is_empty() {
+                    combat_log.add(LogEntry::PartyDefeated);
+                    combat_state.transition(Transition::EndEncounter);
+                } else if enemies_dead {
+                    for survivor in survivors.iter() {
+                        let survivor = &combat_state.combatants[*survivor];
+                        if let Some(dice_pool) = dice_pools.get_mut(*survivor) {
+                            let mut used_dice = vec![];
+                            used_dice.append(&mut dice_pool.rolled);
+                            if let Some(defender) = defenders.get_mut(*survivor) {
+                                used_dice.append(&mut defender.prepped_defense);
+                            }
+                            let mut unused_dice = vec![];
+                            unused_dice.append(&mut dice_pool.drafted);
+                            if let Some(heavy_attacker) = heavy_attackers.get_mut(*survivor) {
+                                unused_dice.append(&mut heavy_attacker.prepped_attack);
+                            }
+                            let name = &names.get(*survivor).unwrap().name;
+                            for die in dice_pool.return_used(used_dice) {
+                                combat_log.add(LogEntry::DieBroke {
+                                    combatant: name.clone(),
+                                    die,
+                                });
+                            }
+                            dice_pool.return_unused(unused_dice);
+                        }
+                    }
+                    combat_log.add(LogEntry::BattleWon {
+                        combatant: names
+                            .get(combat_state.combatants[survivors[0]])
+                            .unwrap()
+                            .name
+                            .clone(),
+                    });
+                    combat_state.transition(Transition::WinBattle(
+                        survivors[0],
+                        generate_rewards(&mut rng),
+                    ));
+                }
+            }
+        }
+
+        // Reward phase: apply the chosen reward to the winner's dice pool, which carries over to the next encounter
+        if let CombatPhase::Reward(winner, rewards) = combat_state.current_phase.clone() {
+            let winner = combat_state.combatants[winner];
+            for ChooseReward(n, die) in reward_choices {
+                if let (Some(reward), Some(dice_pool)) =
+                    (rewards.get(n), dice_pools.get_mut(winner))
+                {
+                    if let Some(die) = apply_reward(dice_pool, reward, die) {
+                        combat_log.add(LogEntry::Reward {
+                            combatant: names.get(winner).unwrap().name.clone(),
+                            reward: reward.clone(),
+                            die,
+                        });
+                        combat_state.transition(Transition::EndEncounter);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.choose_reward_reader = Some(
+            world
+                .fetch_mut::<EventChannel<ChooseReward>>()
+                .register_reader(),
+        );
+    }
+}
+
+fn random_die(rng: &mut DiceRng) -> Die {
+    let sides = *rng.choose(&REWARD_SIDES);
+    Die::standard(*rng.choose(&REWARD_COLORS), sides)
+}
+
+pub fn generate_rewards(rng: &mut DiceRng) -> Vec<Reward> {
+    vec![
+        Reward::NewDie(random_die(rng)),
+        Reward::NewDie(random_die(rng)),
+        Reward::UpgradeSides(2),
+        Reward::ChangeColor(*rng.choose(&REWARD_COLORS)),
+        Reward::RemoveDie,
+    ]
+}
+
+/// Apply a reward to the dice pool, returning the die it changed (or added, or removed) for the combat log.
+/// Returns None if the reward can't be applied, e.g. because it needs a die that doesn't exist.
+fn apply_reward(dice_pool: &mut DicePool, reward: &Reward, die: Option<usize>) -> Option<Die> {
+    if let Reward::NewDie(new_die) = reward {
+        dice_pool.available.push(new_die.clone());
+        return Some(new_die.clone());
+    }
+
+    let n = die.filter(|n| *n < dice_pool.available.len())?;
+    match reward {
+        Reward::UpgradeSides(sides) => {
+            let die = &mut dice_pool.available[n];
+            let max = die.max_face().map_or(0, |face| face.attack_value());
+            die.faces.extend((max + 1..=max + sides).map(Face::Number));
+            Some(die.clone())
+        }
+        Reward::ChangeColor(color) => {
+            let die = &mut dice_pool.available[n];
+            die.color = *color;
+            Some(die.clone())
+        }
+        Reward::RemoveDie => {
+            // always keep enough dice to draft a full hand
+            if dice_pool.available.len() <= dice_pool.max_draft_amount {
+                return None;
+            }
+            Some(dice_pool.available.remove(n))
+        }
+        Reward::NewDie(_) => None,
+    }
+}
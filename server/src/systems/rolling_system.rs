@@ -1,12 +1,20 @@
 use crate::combat_state::CombatPhase;
 use crate::combat_state::CombatState;
+use crate::combat_state::Transition;
 use crate::components::DicePool;
+use crate::components::Enemy;
 use crate::components::Named;
 use crate::log::CombatLog;
-use quad_rand as qrand;
+use crate::rng::DiceRng;
+use crate::shared::Die;
+use crate::shared::Face;
+use crate::shared::LogEntry;
+use crate::shared::MAX_EXPLOSIONS;
 use specs::ReadStorage;
+use specs::Write;
 use specs::WriteExpect;
 use specs::WriteStorage;
+use tracing::debug;
 
 use specs::System;
 
@@ -15,36 +23,57 @@ pub struct RollingSystem;
 impl<'a> System<'a> for RollingSystem {
     type SystemData = (
         ReadStorage<'a, Named>,
+        ReadStorage<'a, Enemy>,
         WriteStorage<'a, DicePool>,
         WriteExpect<'a, CombatState>,
-        WriteExpect<'a, CombatLog>,
+        Write<'a, CombatLog>,
+        WriteExpect<'a, DiceRng>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (names, mut dice_pools, mut combat_state, mut combat_log) = data;
+        let (names, enemies, mut dice_pools, mut combat_state, mut combat_log, mut rng) = data;
         let current_entity = combat_state.combatants[combat_state.current_character];
 
         if let CombatPhase::Roll = &combat_state.current_phase {
             if let Some(dice_pool) = dice_pools.get_mut(current_entity) {
-                println!("Player drafted {:?}", dice_pool.drafted);
+                debug!(drafted = ?dice_pool.drafted, "Player drafted");
+                let name = &names.get(current_entity).unwrap().name;
+                let side = if enemies.contains(current_entity) {
+                    1
+                } else {
+                    0
+                };
                 for die in dice_pool.drafted.iter() {
-                    let mut rolled_die = *die;
-                    rolled_die.rolled_value = Some(qrand::gen_range(1, die.sides + 1));
+                    let mut rolled_die = die.clone();
+                    let mut face = roll_face(die, &mut rng);
+                    rolled_die.rolled_value = Some(face);
+                    while die.rules.exploding
+                        && Some(face) == die.max_face()
+                        && rolled_die.exploded.len() < MAX_EXPLOSIONS
+                    {
+                        face = roll_face(die, &mut rng);
+                        rolled_die.exploded.push(face);
+                        combat_log.add(LogEntry::Exploded {
+                            combatant: name.clone(),
+                            side,
+                            die: die.clone(),
+                            face,
+                        });
+                    }
                     dice_pool.rolled.push(rolled_die);
                 }
                 dice_pool.drafted.clear();
-                combat_state.current_phase = CombatPhase::SelectAction(vec![]);
-                combat_log.add(format!(
-                    "{} rolled [{}]",
-                    names.get(current_entity).unwrap().name,
-                    dice_pool
-                        .rolled
-                        .iter()
-                        .map(|die| { die.to_string() })
-                        .collect::<Vec<String>>()
-                        .join(",")
-                ));
+                combat_state.transition(Transition::Rolled);
+                combat_log.add(LogEntry::Rolled {
+                    combatant: name.clone(),
+                    side,
+                    dice: dice_pool.rolled.clone(),
+                });
             }
         }
     }
 }
+
+fn roll_face(die: &Die, rng: &mut DiceRng) -> Face {
+    *rng.choose(&die.faces)
+}
@@ -0,0 +1,65 @@
+use crate::components::Defender;
+use crate::components::DicePool;
+use crate::components::Named;
+use crate::events::TurnStarted;
+use crate::log::CombatLog;
+use crate::shared::LogEntry;
+use specs::shrev::EventChannel;
+use specs::Read;
+use specs::ReadStorage;
+use specs::ReaderId;
+use specs::SystemData;
+use specs::World;
+use specs::Write;
+use specs::WriteStorage;
+
+use specs::System;
+
+/// Gets a combatant ready for their turn as soon as it starts, by cleaning up leftover state from their
+/// last turn: rolled and prepped defense dice go back to the available dice. Fragile dice were used up by
+/// last turn's action, so they are removed from the pool instead.
+/// Runs after CombatLogSystem, so any broken dice are logged after the turn starting.
+#[derive(Default)]
+pub struct TurnSystem {
+    turn_started_reader: Option<ReaderId<TurnStarted>>,
+}
+
+impl<'a> System<'a> for TurnSystem {
+    type SystemData = (
+        Read<'a, EventChannel<TurnStarted>>,
+        ReadStorage<'a, Named>,
+        WriteStorage<'a, Defender>,
+        WriteStorage<'a, DicePool>,
+        Write<'a, CombatLog>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (turn_started_events, names, mut defenders, mut dice_pools, mut combat_log) = data;
+
+        for event in turn_started_events.read(self.turn_started_reader.as_mut().unwrap()) {
+            if let Some(dice_pool) = dice_pools.get_mut(event.combatant) {
+                let name = &names.get(event.combatant).unwrap().name;
+                let mut used_dice = vec![];
+                used_dice.append(&mut dice_pool.rolled);
+                if let Some(defender) = defenders.get_mut(event.combatant) {
+                    used_dice.append(&mut defender.prepped_defense);
+                }
+                for die in dice_pool.return_used(used_dice) {
+                    combat_log.add(LogEntry::DieBroke {
+                        combatant: name.clone(),
+                        die,
+                    });
+                }
+            }
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.turn_started_reader = Some(
+            world
+                .fetch_mut::<EventChannel<TurnStarted>>()
+                .register_reader(),
+        );
+    }
+}
@@ -1,213 +1,560 @@
-use std::cmp::Reverse;
+use crate::accounts::*;
+use crate::admin;
+use crate::admin::{json_response, text_response};
+use crate::campaign::Campaign;
 use crate::combat_state::*;
-use crate::combatant::*;
-use crate::components::*;
-use crate::events::*;
-use crate::log::*;
+use crate::config::Config;
+use crate::matchmaking::*;
+use crate::metrics;
+use crate::players::*;
+use crate::pvp::{PvpEncounter, SEATS};
+use crate::room::*;
+use crate::save::load_rooms;
 use crate::shared::*;
-use crate::systems::*;
-use specs::RunNow;
-use specs::World;
 use specs::WorldExt;
+use std::collections::HashMap;
+use std::panic;
+use std::panic::AssertUnwindSafe;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::PoisonError;
+use std::time::SystemTime;
+use tracing::{debug, error, info, info_span, trace, warn, Span};
+use ws::util::Token;
 use ws::Handshake;
-use ws::{CloseCode, Handler, Message, Request, Response, Result, Sender};
+use ws::{CloseCode, Factory, Handler, Message, Request, Response, Result, Sender, WebSocket};
 
 pub struct Server {
-    pub world: specs::World,
-    // TODO: when we have multi-player, this would be a HashMap<Player, ClientGameState> or something like that
-    pub materialized_state: ClientGameState,
+    pub rooms: HashMap<String, Room>,
+    pub campaign: Campaign,     // played by new rooms
+    pub pvp_campaign: Campaign, // played by new PvP rooms, from the PvP encounter
+    pub queue: MatchQueue,
+    pub players: PlayerStore,
+    pub signer: TokenSigner,
+    pub config: Config,
+}
+
+/// Listen on the server's configured address, but don't handle connections until it's `run`.
+/// With port 0, any free port is picked, which `local_addr` tells.
+// the error is ws's own, as returned by the rest of its API
+#[allow(clippy::result_large_err)]
+pub fn bind(server: Server) -> Result<WebSocket<impl Factory<Handler = Connection>>> {
+    let settings = server.config.room_settings();
+    let address = server.config.address();
+    // ws runs all connection handlers on a single thread, so the Server never actually crosses threads
+    #[allow(clippy::arc_with_non_send_sync)]
+    let server = Arc::new(Mutex::new(server));
+    WebSocket::new(move |out| Connection {
+        server: Arc::clone(&server),
+        out,
+        room: DEFAULT_ROOM.to_owned(),
+        role: Role::Player(None),
+        player: None,
+        settings,
+    })?
+    .bind(address)
 }
 
 pub struct Connection {
     pub out: Sender,
     pub server: Arc<Mutex<Server>>,
+    pub room: String,
+    pub role: Role,
+    pub settings: RoomSettings, // only used if this connection creates the room
+    pub player: Option<String>, // id of the guest player, from their token or given to them on connect
 }
 
 impl Handler for Connection {
     fn on_request(&mut self, req: &Request) -> Result<Response> {
-        match req.resource() {
-            "/ws" => Response::from_request(req),
-            _ => Ok(Response::new(
-                200,
-                "OK",
-                b"Websocket server is running".to_vec(),
-            )),
+        // Clients join a room with `/ws/<room code>`, or the default room with just `/ws`.
+        // A new room can be given a turn timer in seconds with `?turn_timer=<seconds>` (0 for no timer),
+        // can hide dice pools from spectators with `?mask_spectators`, and can keep rolled and prepped
+        // dice face down with `?face_down_prep`, and can be a PvP match with `?pvp`.
+        // Players can ask for a seat in a PvP match with `?seat=<seat>`, e.g. after matchmaking, unless it belongs
+        // to another player.
+        // Clients join as read-only spectators with `?spectate`, and identify their player with `?token=<token>`
+        // (a new guest player gets a token once connected).
+        // Besides the websocket, the server has a few plain HTTP routes:
+        // - `/healthz` answers once the server is up, for Cloud Run's health checks
+        // - `/metrics` has connection, room and message counts in the Prometheus text format
+        // - `/leaderboard` is the PvP leaderboard as JSON
+        // - `/admin/...` is for looking into and unsticking rooms, see `admin::handle`
+        let (path, query) = match req.resource().split_once('?') {
+            Some((path, query)) => (path, query),
+            None => (req.resource(), ""),
+        };
+        let code = match path {
+            "/healthz" => return Ok(text_response(200, "OK", "ok")),
+            "/metrics" => {
+                let mut response = text_response(200, "OK", &metrics::render(&lock(&self.server)));
+                response.headers_mut().push((
+                    "Content-Type".to_owned(),
+                    b"text/plain; version=0.0.4".to_vec(),
+                ));
+                return Ok(response);
+            }
+            "/leaderboard" => {
+                return Ok(json_response(&lock(&self.server).players.leaderboard()));
+            }
+            path if path == "/admin" || path.starts_with("/admin/") => {
+                return Ok(admin::handle(&mut lock(&self.server), req, path));
+            }
+            "/ws" => DEFAULT_ROOM,
+            path if path.starts_with("/ws/") => &path["/ws/".len()..],
+            "/" => return Ok(text_response(200, "OK", "Websocket server is running")),
+            _ => return Ok(text_response(404, "Not Found", "Not found")),
+        };
+        if !is_valid_room_code(code) {
+            return Ok(Response::new(
+                400,
+                "Bad Request",
+                b"Invalid room code".to_vec(),
+            ));
+        }
+        if !lock(&self.server).has_room_for(code) {
+            return Ok(text_response(
+                503,
+                "Service Unavailable",
+                "The server is full, try again later",
+            ));
+        }
+        self.room = code.to_owned();
+        for param in query.split('&').filter(|param| !param.is_empty()) {
+            match (
+                param,
+                param.strip_prefix("turn_timer=").map(str::parse::<u64>),
+            ) {
+                (_, Some(Ok(0))) => self.settings.turn_timer = None,
+                (_, Some(Ok(seconds))) => self.settings.turn_timer = Some(seconds),
+                ("spectate", _) => self.role = Role::Spectator,
+                ("mask_spectators", _) => self.settings.mask_spectators = true,
+                ("face_down_prep", _) => self.settings.face_down_prep = true,
+                ("pvp", _) => self.settings.pvp = true,
+                // an invalid token just gets the client a new guest player
+                (param, _) if param.starts_with("token=") => {
+                    let server = lock(&self.server);
+                    self.player = server.signer.verify(&param["token=".len()..]);
+                }
+                (param, _) if param.starts_with("seat=") => {
+                    match param["seat=".len()..].parse::<usize>() {
+                        Ok(seat) if seat < SEATS => self.role = Role::Player(Some(seat)),
+                        _ => {
+                            return Ok(Response::new(
+                                400,
+                                "Bad Request",
+                                b"Invalid query parameter".to_vec(),
+                            ))
+                        }
+                    }
+                }
+                _ => {
+                    return Ok(Response::new(
+                        400,
+                        "Bad Request",
+                        b"Invalid query parameter".to_vec(),
+                    ))
+                }
+            }
         }
+        // a PvP seat stays with its player, so nobody else can take over their match
+        if let Role::Player(Some(seat)) = self.role {
+            let server = lock(&self.server);
+            let owner = server
+                .rooms
+                .get(&self.room)
+                .and_then(|room| room.seat_owner(seat));
+            if owner.is_some() && owner != self.player {
+                return Ok(text_response(
+                    403,
+                    "Forbidden",
+                    "That seat belongs to another player",
+                ));
+            }
+        }
+        Response::from_request(req)
     }
 
     fn on_open(&mut self, _: Handshake) -> Result<()> {
-        let server = self.server.lock().unwrap();
+        let _span = self.span().entered();
+        let mut server = lock(&self.server);
+        if self.player.is_none() {
+            let player = new_player_id();
+            info!(%player, "New guest player");
+            self.player = Some(player);
+        }
+        if let Some(player) = &self.player {
+            send_profile(&server, &self.out, player);
+        }
+        let room = server.room(&self.room, self.settings);
+        self.role = room.join(self.out.clone(), self.role, self.player.clone());
         // send initial state to client
-        let combat_state = server.world.read_resource::<CombatState>();
-        println!("Server state: {:?}", *combat_state);
-        println!(
-            "Sending initial state to client: {:?}",
-            server.materialized_state.clone()
-        );
+        debug!(combat_state = ?*room.world.read_resource::<CombatState>(), "Server state");
+        trace!(state = ?room.materialized_state, "Sending initial state to client");
         let server_msg: Message =
-            serde_json::to_string(&ServerMessage::NewState(server.materialized_state.clone()))
+            serde_json::to_string(&ServerMessage::NewState(room.client_state(self.role)))
                 .unwrap()
                 .into();
-        self.out.send(server_msg)
+        self.out.send(server_msg)?;
+        room.schedule_turn_timer();
+        Ok(())
     }
 
     // Handle messages recieved in the websocket (in this case, only on `/ws`).
     fn on_message(&mut self, msg: Message) -> Result<()> {
-        let client_id: usize = self.out.token().into();
+        let _span = self.span().entered();
 
-        let server_msg = if msg.is_text() {
-            Some(self.handle_text_message(client_id, msg))
-        } else {
-            None
-        };
+        if !msg.is_text() {
+            return Ok(());
+        }
+        metrics::count(&metrics::MESSAGES);
+        // a bug handling one message shouldn't take the whole server (and every other room) down with it
+        let result = panic::catch_unwind(AssertUnwindSafe(|| self.handle_text_message(msg)));
+        match result {
+            Ok(Ok(true)) => {}
+            Ok(Ok(false)) => return Ok(()),
+            Err(_) => {
+                error!("Panicked handling message");
+                let server_msg =
+                    ServerMessage::Error("Something went wrong on the server".to_owned());
+                return self.out.send(serde_json::to_string(&server_msg).unwrap());
+            }
+            Ok(Err(error)) => {
+                info!(%error, "Rejected message");
+                let server_msg = ServerMessage::Error(error);
+                return self.out.send(serde_json::to_string(&server_msg).unwrap());
+            }
+        }
 
-        // Broadcast to all connections.
-        // TODO: need to change this to be specific to each client (when multiplayer)
+        debug!("Handled client message");
 
-        println!("Handled client message");
+        // Broadcast to all connections in the same room, each getting their own view of it.
+        let mut server = lock(&self.server);
+        let room = server.room(&self.room, self.settings);
+        debug!(combat_state = ?*room.world.read_resource::<CombatState>(), "Server state");
+        trace!(state = ?room.materialized_state, "Sending new state to clients");
+        room.broadcast();
+        room.schedule_turn_timer();
+        Ok(())
+    }
 
-        let server = self.server.lock().unwrap();
-        let combat_state = server.world.read_resource::<CombatState>();
-        println!("Server state: {:?}", *combat_state);
-        println!(
-            "Sending new state to client: {:?}",
-            server.materialized_state.clone()
-        );
-        server_msg.map_or(Ok(()), |msg| self.out.broadcast(msg))
+    fn on_timeout(&mut self, event: Token) -> Result<()> {
+        let _span = self.span().entered();
+        let mut server = lock(&self.server);
+        if event == MATCHMAKING {
+            if server.queue.time_out(self.out.token()) {
+                info!("Matchmaking timed out");
+                send_matchmaking(&self.out, MatchmakingStatus::TimedOut);
+            }
+            return Ok(());
+        }
+        if event != TURN_TIMER {
+            return Ok(());
+        }
+        if let Some(room) = server.rooms.get_mut(&self.room) {
+            if room.check_turn_timer() {
+                if let Err(err) = room.save() {
+                    error!(room = %room.code, %err, "Could not save room");
+                }
+                room.broadcast();
+                room.schedule_turn_timer();
+                server.record_finished_match(&self.room);
+            }
+        }
+        Ok(())
     }
 
     fn on_close(&mut self, code: CloseCode, reason: &str) {
-        let client_id: usize = self.out.token().into();
+        let _span = self.span().entered();
         let code_number: u16 = code.into();
-        println!(
-            "WebSocket closing - client: {}, code: {} {:?}, reason: {}",
-            client_id, code_number, code, reason
-        );
+        info!(code = code_number, ?code, reason, "Websocket closing");
+        let mut server = lock(&self.server);
+        server.queue.cancel(self.out.token());
+        if let Some(room) = server.rooms.get_mut(&self.room) {
+            room.clients
+                .retain(|client| client.sender.token() != self.out.token());
+            // once everyone has left a finished game, nobody is coming back to it
+            if room.clients.is_empty() && room.is_over() {
+                server.remove_room(&self.room);
+            } else {
+                // someone else checks the turn timer, if it was this client's job
+                room.schedule_turn_timer();
+            }
+        }
     }
 }
 
 impl Server {
-    pub fn new() -> Self {
-        // Setup specs world
-        let mut world = World::new();
-        world.register::<Named>();
-        world.register::<Health>();
-        world.register::<LightAttacker>();
-        world.register::<HeavyAttacker>();
-        world.register::<Defender>();
-        world.register::<DicePool>();
-        let combatants = create_combatants(&mut world);
-
-        let combat_state = CombatState::new(combatants);
-
-        // Insert global resources
-        world.insert(combat_state);
-        world.insert(EventQueue {
-            ..Default::default()
-        });
-        world.insert(CombatLog {
-            ..Default::default()
-        });
-
-        let initial_state = get_materialized_state(&mut world);
-        let mut server = Server {
-            world,
-            materialized_state: initial_state,
+    /// Load the campaign and PvP encounter files and the players, and restore any rooms that were saved
+    /// before the server last stopped. Fails if either of the files is missing or invalid, or the players
+    /// file is corrupt.
+    pub fn new(config: Config) -> std::result::Result<Self, String> {
+        let campaign = Campaign::load(&config.campaign_file)?;
+        let pvp_campaign = PvpEncounter::load(&config.pvp_encounter_file)?.to_campaign();
+        let rooms = load_rooms(&config.save_dir)
+            .into_iter()
+            .map(|(code, save)| {
+                let room = Room::from_save(&code, save, &config);
+                (code, room)
+            })
+            .collect();
+        Ok(Server {
+            rooms,
+            campaign,
+            pvp_campaign,
+            queue: MatchQueue::default(),
+            players: PlayerStore::load(&config.players_file())?,
+            signer: TokenSigner::load(&config.token_secret_file()),
+            config,
+        })
+    }
+
+    /// Get the room with the given code, creating a new game for it if it doesn't exist yet.
+    pub fn room(&mut self, code: &str, settings: RoomSettings) -> &mut Room {
+        let seed = self.seed();
+        let campaign = if settings.pvp {
+            &self.pvp_campaign
+        } else {
+            &self.campaign
         };
+        let config = &self.config;
+        self.rooms
+            .entry(code.to_owned())
+            .or_insert_with(|| Room::new(code, seed, settings, campaign.clone(), config))
+    }
 
-        println!("CREATED INITIAL GAME WORLD!!!");
-
-        server.game_loop();
-
-        server
-    }
-
-    fn game_loop(&mut self) {
-        // since some game loop iterations create events that get handled on next iteration,
-        // we keep looping until materialized state does not change
-        loop {
-            // run ECS systems
-            let mut drafting_system = DraftingSystem {};
-            let mut rolling_system = RollingSystem {};
-            let mut action_system = ActionSystem {};
-            let mut materialize_system = MaterializeSystem {};
-            drafting_system.run_now(&self.world);
-            rolling_system.run_now(&self.world);
-            action_system.run_now(&self.world);
-            materialize_system.run_now(&self.world);
-            self.world.maintain();
-
-            // handle events
-            {
-                let mut event_queue = self.world.write_resource::<EventQueue>();
-                if !event_queue.events.is_empty() {
-                    println!("current events: {:?}", event_queue.events);
-                }
-                if !event_queue.new_events.is_empty() {
-                    println!("new events: {:?}", event_queue.new_events);
-                }
-                event_queue.events = (*event_queue.new_events).to_vec();
-                event_queue.new_events.clear();
+    /// Whether a client can join the room: it either exists already, or there's space for another one.
+    pub fn has_room_for(&mut self, code: &str) -> bool {
+        self.rooms.contains_key(code) || self.has_space()
+    }
+
+    /// Whether there's space for another room, throwing away abandoned rooms (see `Room::is_abandoned`)
+    /// to make some if the server is full.
+    pub fn has_space(&mut self) -> bool {
+        if self.rooms.len() >= self.config.max_rooms {
+            let abandoned: Vec<String> = self
+                .rooms
+                .values()
+                .filter(|room| room.is_abandoned())
+                .map(|room| room.code.clone())
+                .collect();
+            for code in abandoned {
+                self.remove_room(&code);
             }
+        }
+        self.rooms.len() < self.config.max_rooms
+    }
 
-            // check if it's time to end this current game loop
-            let old_state: String = serde_json::to_string(&self.materialized_state).unwrap();
-            self.materialized_state = get_materialized_state(&mut self.world);
-            if serde_json::to_string(&self.materialized_state).unwrap() == old_state {
-                println!("Game state stabilized, ending game loop for now.");
-                break;
+    /// Throw a room away along with its save, so it doesn't take up space after a restart either.
+    pub fn remove_room(&mut self, code: &str) {
+        if let Some(room) = self.rooms.remove(code) {
+            if let Err(err) = room.delete_save() {
+                error!(room = code, %err, "Could not delete saved room");
             }
+            info!(room = code, "Removed room");
         }
     }
-}
 
-impl Connection {
-    fn handle_text_message(&mut self, client_id: usize, msg: Message) -> Message {
-        let txt = &msg.into_text().unwrap();
-        let client_msg: ClientMessage = serde_json::from_str(txt).unwrap();
+    /// Seed for a new room's dice, which is the current timestamp unless the config fixes it.
+    fn seed(&self) -> u64 {
+        self.config.rng_seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        })
+    }
+
+    /// Record the result of the room's PvP match if it just ended, updating both players' ratings.
+    pub fn record_finished_match(&mut self, code: &str) {
+        let finished = match self.rooms.get_mut(code).and_then(Room::take_finished_match) {
+            Some(finished) => finished,
+            None => return,
+        };
+        match self.players.record(finished) {
+            Some(record) => info!(
+                room = code,
+                winner = %record.players[record.winner],
+                rating_changes = ?record.rating_changes,
+                "Recorded match"
+            ),
+            None => {
+                info!(
+                    room = code,
+                    "Match not recorded, since not every player has an id"
+                );
+                return;
+            }
+        }
+        self.save_players();
+    }
+
+    /// Save every guest player's profile, logging rather than failing if it can't be written.
+    pub fn save_players(&self) {
+        if let Err(err) = self.players.save(&self.config.players_file()) {
+            error!(%err, "Could not save players file");
+        }
+    }
 
-        println!(
-            "Server received text message\ntext: '{}'\nfrom: '{}'\n",
-            txt, client_id
+    /// Create a PvP room for two players who were paired up by matchmaking, with the seats kept for
+    /// the given players. Returns the room's code.
+    pub fn create_match_room(&mut self, players: Vec<Option<String>>) -> String {
+        let time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap();
+        let mut n = time.as_nanos();
+        while self.rooms.contains_key(&format!("match-{:x}", n)) {
+            n += 1;
+        }
+        let code = format!("match-{:x}", n);
+        let settings = RoomSettings {
+            pvp: true,
+            ..self.config.room_settings()
+        };
+        let mut room = Room::new(
+            &code,
+            self.seed(),
+            settings,
+            self.pvp_campaign.clone(),
+            &self.config,
         );
+        room.reserve_seats(players);
+        self.rooms.insert(code.clone(), room);
+        code
+    }
+}
+
+impl Connection {
+    /// Span for everything logged while handling this connection's events.
+    fn span(&self) -> Span {
+        info_span!("client", client = usize::from(self.out.token()), room = %self.room)
+    }
 
-        let mut server = self.server.lock().unwrap();
+    /// Returns whether the message changed the room, so it needs to be broadcast.
+    fn handle_text_message(&mut self, msg: Message) -> std::result::Result<bool, String> {
+        let txt = &msg.into_text().unwrap();
+        debug!(text = %txt, "Received message");
+        let client_msg: ClientMessage =
+            serde_json::from_str(txt).map_err(|err| format!("Invalid message: {}", err))?;
 
-        // dispatch event/etc. based on incoming message
+        let mut server = lock(&self.server);
         match client_msg {
-            ClientMessage::FinishDrafting(draft_choices) => {
-                // User is finished drafting. Send all their draft choices into our Drafting system.
+            ClientMessage::FindMatch => {
+                if !server.has_space() {
+                    return Err("The server is full, try again later".to_owned());
+                }
+                let rating = self
+                    .player
+                    .as_ref()
+                    .map(|player| server.players.rating(player));
+                self.find_match(&mut server, rating);
+                return Ok(false);
+            }
+            ClientMessage::SetName(name) => {
+                if let Some(player) = &self.player {
+                    server.players.set_name(player, &name)?;
+                    server.save_players();
+                    send_profile(&server, &self.out, player);
+                }
+                return Ok(false);
+            }
+            ClientMessage::CancelFindMatch => {
+                if server.queue.cancel(self.out.token()) {
+                    send_matchmaking(&self.out, MatchmakingStatus::Cancelled);
+                }
+                return Ok(false);
+            }
+            _ => {}
+        }
+        let room = server.room(&self.room, self.settings);
+        room.receive(self.role, client_msg)?;
+        // save after every change, so the room survives a server restart
+        if let Err(err) = room.save() {
+            error!(room = %room.code, %err, "Could not save room");
+        }
+        server.record_finished_match(&self.room);
+        let unlocked_dice = server
+            .rooms
+            .get_mut(&self.room)
+            .map_or(vec![], Room::take_unlocked_dice);
+        if let (Some(player), false) = (&self.player, unlocked_dice.is_empty()) {
+            info!(%player, dice = unlocked_dice.len(), "Unlocked dice");
+            server.players.unlock_dice(player, unlocked_dice);
+            server.save_players();
+            send_profile(&server, &self.out, player);
+        }
+        Ok(true)
+    }
+
+    /// Join the matchmaking queue, or start a match with whoever has been waiting. The clients are told
+    /// which room and seat to join, since the match is played on new connections to that room.
+    fn find_match(&self, server: &mut Server, rating: Option<u32>) {
+        match server
+            .queue
+            .find_match(self.out.clone(), self.player.clone(), rating)
+        {
+            None => {
+                if let Err(err) = self
+                    .out
+                    .timeout(MATCHMAKING_TIMEOUT * 1000 + 100, MATCHMAKING)
                 {
-                    let mut event_queue = server.world.write_resource::<EventQueue>();
-                    // process draft choices in reverse index order so we don't invalidate the indexes
-                    let mut sorted_choices = draft_choices;
-                    sorted_choices.sort_by_key(|&b| Reverse(b));
-                    for choice in sorted_choices {
-                        event_queue.new_events.push(Event::DraftDie(choice));
-                    }
+                    error!(%err, "Could not schedule matchmaking timeout");
                 }
-                // Let game loop process the new drafted dice before transitioning to next phase.
-                server.game_loop();
-                // And then transition the combat phase to Rolling
-                let mut combat_state = server.world.write_resource::<CombatState>();
-                combat_state.current_phase = CombatPhase::Roll
+                send_matchmaking(&self.out, MatchmakingStatus::Searching);
+            }
+            Some(QueuedPlayer {
+                sender: opponent,
+                player: opponent_player,
+                ..
+            }) => {
+                // whoever waited longer gets the first turn
+                let room = server.create_match_room(vec![opponent_player, self.player.clone()]);
+                info!(
+                    opponent = usize::from(opponent.token()),
+                    match_room = %room,
+                    "Matched clients"
+                );
+                send_matchmaking(
+                    &opponent,
+                    MatchmakingStatus::Found {
+                        room: room.clone(),
+                        seat: 0,
+                    },
+                );
+                send_matchmaking(&self.out, MatchmakingStatus::Found { room, seat: 1 });
             }
         }
+    }
+}
 
-        server.game_loop();
+/// Lock the server, even if a panic while handling an earlier message left the lock poisoned.
+fn lock(server: &Mutex<Server>) -> MutexGuard<'_, Server> {
+    server.lock().unwrap_or_else(PoisonError::into_inner)
+}
 
-        let server_msg: Message =
-            serde_json::to_string(&ServerMessage::NewState(server.materialized_state.clone()))
-                .unwrap()
-                .into();
+/// Send the player their profile, along with the token to identify themselves with next time.
+fn send_profile(server: &Server, out: &Sender, player: &str) {
+    let server_msg = ServerMessage::Profile {
+        token: server.signer.issue(player),
+        profile: server.players.profile(player),
+    };
+    if let Err(err) = out.send(serde_json::to_string(&server_msg).unwrap()) {
+        warn!(%err, "Could not send profile");
+    }
+}
 
-        server_msg
+fn send_matchmaking(out: &Sender, status: MatchmakingStatus) {
+    let server_msg = ServerMessage::Matchmaking(status);
+    if let Err(err) = out.send(serde_json::to_string(&server_msg).unwrap()) {
+        warn!(%err, "Could not send matchmaking status");
     }
 }
 
-fn get_materialized_state(world: &mut specs::World) -> ClientGameState {
-    let combat_state = world.read_resource::<CombatState>();
-    combat_state.materialized_state.clone()
+/// Room codes are used as save file names, so only allow simple characters.
+fn is_valid_room_code(code: &str) -> bool {
+    !code.is_empty()
+        && code.len() <= 32
+        && code
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
 }
@@ -0,0 +1,370 @@
+mod common;
+
+use common::Battle;
+use dice_combat::shared::{
+    ClientAction, ClientMessage, ClientPhase, Face, LogEntry, Prep, Reward, MAX_EXPLOSIONS,
+};
+
+// positions of the combatants in the Ambush encounter of the skirmish roster: the party, then the enemies
+const HERO: usize = 0;
+const SQUIRE: usize = 1;
+const GOBLIN: usize = 2;
+const ORC: usize = 3;
+
+// positions of the actions each combatant can choose from, in the order ActionSystem lists them
+const LIGHT_ATTACK: usize = 0;
+const HERO_HEAVY_ATTACK: usize = 1; // or prepping one, if there's none prepped yet
+const HERO_DEFEND: usize = 2;
+const ORC_DEFEND: usize = 1;
+
+const THREE: Face = Face::Number(3);
+
+/// The skirmish roster's run, just after entering its first battle.
+fn ambush() -> Battle {
+    let mut battle = Battle::campaign("skirmish");
+    battle.play([ClientMessage::ChooseNode(0)]);
+    battle.new_log();
+    battle
+}
+
+/// Play the current combatant's whole turn: draft dice with the given faces, and light attack the target.
+fn light_attack(battle: &mut Battle, faces: &[Face], target: usize) {
+    let draft = battle.draft(faces);
+    battle.play([
+        draft,
+        ClientMessage::ChooseAction(LIGHT_ATTACK),
+        ClientMessage::ChooseTarget(target),
+    ]);
+}
+
+/// Rounds and combatants of the turns started in the given log entries.
+fn turns(log: &[LogEntry]) -> Vec<(usize, &str)> {
+    log.iter()
+        .filter_map(|entry| match entry {
+            LogEntry::TurnStarted { round, combatant } => Some((*round, combatant.as_str())),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn entering_a_battle_starts_the_first_turn() {
+    let mut battle = Battle::campaign("skirmish");
+    assert!(matches!(
+        battle.state().client_phase,
+        ClientPhase::SelectNode(0, 2, _)
+    ));
+
+    battle.play([ClientMessage::ChooseNode(0)]);
+
+    let log = battle.new_log();
+    assert!(matches!(
+        &log[..],
+        [LogEntry::EnteredNode(node), LogEntry::TurnStarted { round: 1, combatant }]
+            if node == "Ambush" && combatant == "Hero"
+    ));
+    assert_eq!(battle.current(), "Hero");
+    let state = battle.state();
+    let names: Vec<&str> = state.combatants.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, ["Hero", "Squire", "Goblin", "Orc"]);
+    assert!(matches!(state.client_phase, ClientPhase::DraftDice(pool, 2) if pool.len() == 3));
+}
+
+#[test]
+fn drafted_dice_get_rolled() {
+    let mut battle = ambush();
+
+    let draft = battle.draft(&[THREE, THREE]);
+    battle.play([draft]);
+
+    let log = battle.new_log();
+    assert!(matches!(
+        &log[..],
+        [LogEntry::Drafted { .. }, LogEntry::Drafted { .. }, LogEntry::Rolled { combatant, dice, .. }]
+            if combatant == "Hero" && dice.len() == 2
+    ));
+    let state = battle.state();
+    match state.client_phase {
+        ClientPhase::SelectAction(rolled, actions) => {
+            assert!(rolled.iter().all(|die| die.rolled_value == Some(THREE)));
+            assert!(matches!(
+                &actions[..],
+                [
+                    ClientAction::LightAttack,
+                    ClientAction::PrepHeavyAttack,
+                    ClientAction::Defend
+                ]
+            ));
+        }
+        phase => panic!("expected SelectAction, got {:?}", phase),
+    }
+    assert_eq!(state.combatants[HERO].pool.len(), 1);
+}
+
+#[test]
+fn drafting_stops_at_the_max_draft_amount() {
+    let mut battle = ambush();
+
+    battle.play([ClientMessage::FinishDrafting(vec![0, 1, 2, 7])]);
+
+    let drafted = battle
+        .new_log()
+        .iter()
+        .filter(|entry| matches!(entry, LogEntry::Drafted { .. }))
+        .count();
+    assert_eq!(drafted, 2);
+    let state = battle.state();
+    assert!(
+        matches!(state.client_phase, ClientPhase::SelectAction(rolled, _) if rolled.len() == 2)
+    );
+    assert_eq!(state.combatants[HERO].pool.len(), 1);
+}
+
+#[test]
+fn exploding_dice_roll_again_on_their_max_face() {
+    let mut battle = ambush();
+    light_attack(&mut battle, &[THREE, THREE], ORC);
+    battle.new_log();
+
+    // the Squire's exploding die only has one face, so it explodes as many times as it's allowed to
+    let draft = battle.draft(&[Face::Number(2)]);
+    battle.play([draft]);
+
+    let log = battle.new_log();
+    let explosions = log
+        .iter()
+        .filter(
+            |entry| matches!(entry, LogEntry::Exploded { combatant, .. } if combatant == "Squire"),
+        )
+        .count();
+    assert_eq!(explosions, MAX_EXPLOSIONS);
+    assert!(matches!(
+        log.last(),
+        Some(LogEntry::Rolled { dice, .. }) if dice[0].exploded.len() == MAX_EXPLOSIONS
+    ));
+
+    battle.play([
+        ClientMessage::ChooseAction(LIGHT_ATTACK),
+        ClientMessage::ChooseTarget(ORC),
+    ]);
+    assert!(matches!(
+        &battle.new_log()[0],
+        LogEntry::Attack { damage, .. } if *damage == 2 * (MAX_EXPLOSIONS + 1)
+    ));
+    assert_eq!(battle.hp("Orc"), 30 - 6 - 2 * (MAX_EXPLOSIONS + 1));
+}
+
+#[test]
+fn light_attack_damages_the_target() {
+    let mut battle = ambush();
+    let draft = battle.draft(&[THREE, THREE]);
+    battle.play([draft, ClientMessage::ChooseAction(LIGHT_ATTACK)]);
+
+    // anyone but the attacker can be targeted, even their own side
+    assert!(matches!(
+        battle.state().client_phase,
+        ClientPhase::SelectTarget(_, ClientAction::LightAttack, targets)
+            if targets == [SQUIRE, GOBLIN, ORC]
+    ));
+    battle.new_log();
+
+    battle.play([ClientMessage::ChooseTarget(GOBLIN)]);
+
+    let log = battle.new_log();
+    assert!(matches!(
+        &log[..],
+        [
+            LogEntry::Attack { attacker, target, heavy: false, damage: 6, blocked: 0 },
+            LogEntry::Death { combatant },
+            LogEntry::TurnStarted { round: 1, .. },
+        ] if attacker == "Hero" && target == "Goblin" && combatant == "Goblin"
+    ));
+    assert_eq!(battle.hp("Goblin"), 0);
+    assert_eq!(battle.current(), "Squire");
+
+    // and the dead can't be targeted
+    let draft = battle.draft(&[Face::Number(1)]);
+    battle.play([draft, ClientMessage::ChooseAction(LIGHT_ATTACK)]);
+    assert!(matches!(
+        battle.state().client_phase,
+        ClientPhase::SelectTarget(_, ClientAction::LightAttack, targets) if targets == [HERO, ORC]
+    ));
+}
+
+#[test]
+fn invalid_targets_are_ignored_and_the_action_can_be_cancelled() {
+    let mut battle = ambush();
+    let draft = battle.draft(&[THREE, THREE]);
+    battle.play([draft, ClientMessage::ChooseAction(LIGHT_ATTACK)]);
+    battle.new_log();
+
+    battle.play([
+        ClientMessage::ChooseTarget(HERO),
+        ClientMessage::ChooseTarget(7),
+    ]);
+    assert!(battle.new_log().is_empty());
+    assert!(matches!(
+        battle.state().client_phase,
+        ClientPhase::SelectTarget(..)
+    ));
+
+    battle.play([ClientMessage::CancelAction]);
+    assert!(matches!(
+        battle.state().client_phase,
+        ClientPhase::SelectAction(rolled, actions) if rolled.len() == 2 && actions.len() == 3
+    ));
+    assert_eq!(battle.current(), "Hero");
+}
+
+#[test]
+fn defense_blocks_damage_until_the_defenders_next_turn() {
+    let mut battle = ambush();
+    let draft = battle.draft(&[Face::Shield(2)]);
+    battle.play([draft, ClientMessage::ChooseAction(HERO_DEFEND)]);
+
+    let log = battle.new_log();
+    assert!(matches!(
+        &log[2..],
+        [
+            LogEntry::Prepped { combatant, prep: Prep::Defense, dice, .. },
+            LogEntry::TurnStarted { round: 1, .. },
+        ] if combatant == "Hero" && dice.len() == 1
+    ));
+    assert_eq!(battle.state().combatants[HERO].prepped_defense.len(), 1);
+
+    light_attack(&mut battle, &[Face::Number(1)], GOBLIN);
+    // every enemy attack on the Hero this round gets 2 damage blocked
+    light_attack(&mut battle, &[Face::Number(2)], HERO);
+    light_attack(&mut battle, &[Face::Number(4)], HERO);
+    let attacks: Vec<(usize, usize)> = battle
+        .new_log()
+        .iter()
+        .filter_map(|entry| match entry {
+            LogEntry::Attack {
+                target,
+                damage,
+                blocked,
+                ..
+            } if target == "Hero" => Some((*damage, *blocked)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(attacks, [(0, 2), (2, 2)]);
+    assert_eq!(battle.hp("Hero"), 18);
+
+    // the defense dice are back in the pool once the Hero's turn comes round again
+    assert_eq!(battle.current(), "Hero");
+    let hero = &battle.state().combatants[HERO];
+    assert!(hero.prepped_defense.is_empty());
+    assert_eq!(hero.pool.len(), 3);
+}
+
+#[test]
+fn heavy_attack_adds_the_dice_prepped_on_an_earlier_turn() {
+    let mut battle = ambush();
+    let draft = battle.draft(&[THREE, THREE]);
+    battle.play([draft, ClientMessage::ChooseAction(HERO_HEAVY_ATTACK)]);
+    assert!(matches!(
+        &battle.new_log()[3],
+        LogEntry::Prepped { prep: Prep::HeavyAttack, dice, .. } if dice.len() == 2
+    ));
+    assert_eq!(battle.current(), "Squire");
+
+    light_attack(&mut battle, &[Face::Number(1)], GOBLIN);
+    light_attack(&mut battle, &[Face::Number(2)], SQUIRE);
+    let draft = battle.draft(&[Face::Shield(3)]);
+    battle.play([draft, ClientMessage::ChooseAction(ORC_DEFEND)]);
+    battle.new_log();
+
+    // the prepped dice aren't in the pool, so only the shield die is left to draft
+    let draft = battle.draft(&[Face::Shield(2)]);
+    battle.play([draft, ClientMessage::ChooseAction(HERO_HEAVY_ATTACK)]);
+    assert!(matches!(
+        battle.state().client_phase,
+        ClientPhase::SelectTarget(_, ClientAction::HeavyAttack(prepped), _) if prepped.len() == 2
+    ));
+
+    battle.play([ClientMessage::ChooseTarget(ORC)]);
+
+    // the shield face does no damage, and the Orc's own shield blocks some of the prepped 3s
+    assert!(matches!(
+        &battle.new_log()[2],
+        LogEntry::Attack {
+            heavy: true,
+            damage: 3,
+            blocked: 3,
+            ..
+        }
+    ));
+    assert_eq!(battle.hp("Orc"), 27);
+    assert!(battle.state().combatants[HERO].prepped_attack.is_empty());
+}
+
+#[test]
+fn turns_rotate_through_the_living_and_then_the_next_round() {
+    let mut battle = ambush();
+
+    light_attack(&mut battle, &[THREE, THREE], GOBLIN);
+    light_attack(&mut battle, &[Face::Number(1)], ORC);
+    light_attack(&mut battle, &[Face::Number(4)], SQUIRE);
+
+    // the dead Goblin is skipped, and the next round starts back at the top
+    assert_eq!(
+        turns(&battle.log()),
+        [(1, "Hero"), (1, "Squire"), (1, "Orc"), (2, "Hero")]
+    );
+    assert_eq!(battle.current(), "Hero");
+}
+
+#[test]
+fn winning_the_battle_gives_a_reward_and_moves_on_through_the_map() {
+    let mut battle = ambush();
+    let squire_attack = [Face::Number(1), Face::Number(2)];
+    light_attack(&mut battle, &[THREE, THREE], GOBLIN);
+    light_attack(&mut battle, &squire_attack, ORC);
+    light_attack(&mut battle, &[Face::Number(4)], HERO);
+    light_attack(&mut battle, &[THREE, THREE], ORC);
+    battle.new_log();
+    light_attack(&mut battle, &squire_attack, ORC);
+
+    let log = battle.new_log();
+    assert!(matches!(
+        &log[log.len() - 2..],
+        [LogEntry::Death { combatant: orc }, LogEntry::BattleWon { combatant: hero }]
+            if orc == "Orc" && hero == "Hero"
+    ));
+    let rewards = match battle.state().client_phase {
+        ClientPhase::ChooseReward(rewards, _) => rewards,
+        phase => panic!("expected ChooseReward, got {:?}", phase),
+    };
+    assert!(!rewards.is_empty());
+
+    battle.play([ClientMessage::ChooseReward(0, Some(0))]);
+    assert!(
+        matches!(&battle.new_log()[..], [LogEntry::Reward { combatant, .. }] if combatant == "Hero")
+    );
+    // the first reward is a new die, which the player unlocks for their profile once
+    let unlocked = battle.room.take_unlocked_dice();
+    match &rewards[0] {
+        Reward::NewDie(die) => assert_eq!(format!("{:?}", unlocked), format!("{:?}", [die])),
+        reward => panic!("expected a new die, got {:?}", reward),
+    }
+    assert!(battle.room.take_unlocked_dice().is_empty());
+    assert!(matches!(
+        battle.state().client_phase,
+        ClientPhase::SelectNode(1, 2, nodes) if nodes[0].name == "Campfire"
+    ));
+
+    // resting on the last floor heals the party, up to their max, and finishes the run
+    battle.play([ClientMessage::ChooseNode(0)]);
+    assert_eq!(battle.hp("Hero"), 20);
+    assert_eq!(battle.hp("Squire"), 10);
+    match battle.state().client_phase {
+        ClientPhase::RunSummary(summary) => {
+            assert!(summary.victory);
+            assert_eq!(summary.encounters_won, 1);
+            assert_eq!(summary.visited, ["Ambush", "Campfire"]);
+        }
+        phase => panic!("expected RunSummary, got {:?}", phase),
+    }
+}
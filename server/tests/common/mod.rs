@@ -0,0 +1,106 @@
+// each test binary only uses some of these helpers
+#![allow(dead_code)]
+
+use dice_combat::campaign::Campaign;
+use dice_combat::config::Config;
+use dice_combat::log::CombatLog;
+use dice_combat::room::{Role, Room, RoomSettings};
+use dice_combat::shared::{ClientGameState, ClientMessage, ClientPhase, Face, LogEntry};
+use specs::WorldExt;
+
+// every room in the tests rolls the same dice, though the rosters mostly use single faced dice anyway
+pub const SEED: u64 = 3;
+
+/// A room playing a campaign run, driven by scripted client messages instead of a websocket.
+/// Messages go through `Room::receive`, just like the ones the server gets from clients.
+pub struct Battle {
+    pub room: Room,
+    seen: usize, // log entries already returned by `new_log`
+}
+
+impl Battle {
+    /// Start a run with the party, enemies and map of `tests/rosters/<roster>.json`, without a turn timer.
+    pub fn campaign(roster: &str) -> Self {
+        let config = Config {
+            campaign_file: format!("tests/rosters/{}.json", roster),
+            ..Config::default()
+        };
+        let settings = RoomSettings {
+            turn_timer: None,
+            ..RoomSettings::default()
+        };
+        let campaign = Campaign::load(&config.campaign_file).unwrap();
+        Battle {
+            room: Room::new("test", SEED, settings, campaign, &config),
+            seen: 0,
+        }
+    }
+
+    /// Send a message as the run's player, who controls every combatant.
+    pub fn send(&mut self, msg: ClientMessage) -> Result<(), String> {
+        self.room.receive(Role::Player(None), msg)
+    }
+
+    /// Send each message in turn, failing the test if any of them is rejected.
+    pub fn play<I: IntoIterator<Item = ClientMessage>>(&mut self, script: I) {
+        for msg in script {
+            let text = serde_json::to_string(&msg).unwrap();
+            if let Err(err) = self.send(msg) {
+                panic!("{} was rejected: {}", text, err);
+            }
+        }
+    }
+
+    /// What the player sees right now.
+    pub fn state(&self) -> ClientGameState {
+        self.room.client_state(Role::Player(None))
+    }
+
+    pub fn log(&self) -> Vec<LogEntry> {
+        self.room.world.read_resource::<CombatLog>().entries.clone()
+    }
+
+    /// Log entries added since the last time this was called, to check what each step of a script did.
+    pub fn new_log(&mut self) -> Vec<LogEntry> {
+        let log = self.log();
+        // a new run starts a new log
+        let seen = if self.seen > log.len() { 0 } else { self.seen };
+        self.seen = log.len();
+        log[seen..].to_vec()
+    }
+
+    /// Name of the combatant whose turn it is.
+    pub fn current(&self) -> String {
+        let state = self.state();
+        let current = state.current_combatant.expect("nobody's turn");
+        state.combatants[current].name.clone()
+    }
+
+    pub fn hp(&self, name: &str) -> usize {
+        let state = self.state();
+        let combatant = state
+            .combatants
+            .iter()
+            .find(|combatant| combatant.name == name);
+        combatant
+            .unwrap_or_else(|| panic!("no {} in the battle", name))
+            .hp
+    }
+
+    /// Message drafting a die with each of the given faces from the current combatant's pool
+    /// (the rosters give most dice a single face), since the pool gets reordered as dice are used.
+    pub fn draft(&self, faces: &[Face]) -> ClientMessage {
+        let pool = match self.state().client_phase {
+            ClientPhase::DraftDice(pool, _) => pool,
+            phase => panic!("not drafting, but in {:?}", phase),
+        };
+        let mut choices: Vec<usize> = vec![];
+        for face in faces {
+            let choice = (0..pool.len())
+                .find(|n| !choices.contains(n) && pool[*n].faces[0] == *face)
+                .unwrap_or_else(|| panic!("no die with {:?} left to draft", face));
+            choices.push(choice);
+        }
+        ClientMessage::FinishDrafting(choices)
+    }
+}
@@ -0,0 +1,57 @@
+// Loading the campaign and PvP encounter files, which the server does once when it starts.
+use dice_combat::campaign::Campaign;
+use dice_combat::pvp::PvpEncounter;
+use serde_json::json;
+
+/// A one battle campaign, with the given dice for its only party member.
+fn campaign_with_dice(dice: serde_json::Value) -> Campaign {
+    serde_json::from_value(json!({
+        "name": "Test",
+        "party": [{ "name": "Hero", "hp": 10, "light_attacker": true, "max_draft_amount": 1, "dice": dice }],
+        "enemies": [{ "name": "Goblin", "hp": 5, "max_draft_amount": 1, "dice": [{ "color": "Red", "sides": 2 }] }],
+        "floors": [[{ "name": "Ambush", "kind": { "Encounter": ["Goblin"] } }]]
+    }))
+    .unwrap()
+}
+
+#[test]
+fn shipped_data_files_are_valid() {
+    Campaign::load("assets/campaign.json").unwrap();
+    PvpEncounter::load("assets/pvp.json").unwrap();
+    Campaign::load("tests/rosters/skirmish.json").unwrap();
+    PvpEncounter::load("tests/rosters/duel.json").unwrap();
+}
+
+#[test]
+fn missing_files_are_errors() {
+    let err = Campaign::load("assets/no-such-campaign.json").unwrap_err();
+    assert!(err.starts_with("Could not read campaign file"));
+}
+
+#[test]
+fn dice_have_to_have_faces() {
+    let valid = campaign_with_dice(json!([
+        { "color": "Red", "sides": 6 },
+        { "color": "Yellow", "faces": [{ "Shield": 2 }, "Blank"] }
+    ]));
+    assert!(valid.validate().is_ok());
+
+    // an empty list of faces is also how a hidden die looks, so it's never a real die
+    for die in [
+        json!({ "color": "Red", "faces": [] }),
+        json!({ "color": "Red", "sides": 0 }),
+        json!({ "color": "Red" }),
+    ] {
+        let err = campaign_with_dice(json!([die])).validate().unwrap_err();
+        assert!(err.starts_with("Hero has an invalid die"), "{}", err);
+    }
+}
+
+#[test]
+fn encounters_need_known_enemies() {
+    let mut campaign = campaign_with_dice(json!([{ "color": "Red", "sides": 6 }]));
+    campaign.enemies.clear();
+
+    let err = campaign.validate().unwrap_err();
+    assert_eq!(err, "Ambush has no enemy named Goblin");
+}
@@ -0,0 +1,509 @@
+// Conformance tests for the websocket protocol: real clients exchanging ClientMessage and ServerMessage
+// frames with a server running in the test process.
+use dice_combat::config::Config;
+use dice_combat::shared::{
+    ClientGameState, ClientMessage, ClientPhase, LogEntry, MatchmakingStatus, ServerMessage,
+};
+use dice_combat::websocket;
+use dice_combat::websocket::Server;
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use ws::{CloseCode, Handler, Handshake, Message, Sender};
+
+// how long to wait for a message that should come, and to listen for one that shouldn't
+const TIMEOUT: Duration = Duration::from_secs(5);
+const QUIET: Duration = Duration::from_millis(300);
+
+/// A server running in the test process, on a free port and with its own temporary directory for saved
+/// rooms and players. It's stopped and the directory deleted when it's dropped, even if the test fails.
+struct TestServer {
+    address: SocketAddr,
+    broadcaster: Sender,
+    dir: PathBuf,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl TestServer {
+    fn start() -> Self {
+        TestServer::start_with(|_| {})
+    }
+
+    /// Start a server with some of its settings changed from the ones every test uses.
+    fn start_with(configure: impl FnOnce(&mut Config)) -> Self {
+        static STARTED: AtomicUsize = AtomicUsize::new(0);
+        let n = STARTED.fetch_add(1, Ordering::SeqCst);
+        let dir = env::temp_dir().join(format!("dice-combat-protocol-{}-{}", process::id(), n));
+        let assets = concat!(env!("CARGO_MANIFEST_DIR"), "/assets");
+        let mut config = Config {
+            listen_address: "127.0.0.1".to_owned(),
+            port: 0,
+            campaign_file: format!("{}/campaign.json", assets),
+            pvp_encounter_file: format!("{}/pvp.json", assets),
+            turn_timer: 0,
+            save_dir: dir.join("saves").to_string_lossy().into_owned(),
+            data_dir: dir.join("data").to_string_lossy().into_owned(),
+            ..Config::default()
+        };
+        configure(&mut config);
+        let (started_tx, started_rx) = mpsc::channel();
+        // the server isn't Send, so it's set up on the thread running it
+        let thread = thread::spawn(move || {
+            let ws = websocket::bind(Server::new(config).unwrap()).unwrap();
+            started_tx
+                .send((ws.local_addr().unwrap(), ws.broadcaster()))
+                .unwrap();
+            ws.run().unwrap();
+        });
+        let (address, broadcaster) = started_rx.recv_timeout(TIMEOUT).unwrap();
+        TestServer {
+            address,
+            broadcaster,
+            dir,
+            thread: Some(thread),
+        }
+    }
+
+    /// Connect to the given path (e.g. `/ws/<room code>?pvp`), returning the client along with the
+    /// state it got on joining.
+    fn join(&self, path: &str) -> (Client, ClientGameState) {
+        Client::join(&format!("ws://{}{}", self.address, path))
+    }
+
+    /// Status line of a plain HTTP request to the server, e.g. for a websocket upgrade it refuses.
+    fn http_status(&self, path: &str) -> String {
+        let mut stream = TcpStream::connect(self.address).unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+        write!(
+            stream,
+            "GET {} HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            path
+        )
+        .unwrap();
+        let mut response = vec![0; 1024];
+        let n = stream.read(&mut response).unwrap();
+        let response = String::from_utf8_lossy(&response[..n]);
+        response.lines().next().unwrap_or_default().to_owned()
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = self.broadcaster.shutdown();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+enum Frame {
+    Text(String),
+    Closed,
+}
+
+/// Forwards everything the server sends to the test.
+struct Forwarder {
+    out: Sender,
+    opened: mpsc::Sender<Sender>,
+    frames: mpsc::Sender<Frame>,
+}
+
+impl Handler for Forwarder {
+    fn on_open(&mut self, _: Handshake) -> ws::Result<()> {
+        self.opened.send(self.out.clone()).unwrap();
+        Ok(())
+    }
+
+    fn on_message(&mut self, msg: Message) -> ws::Result<()> {
+        let _ = self.frames.send(Frame::Text(msg.into_text()?));
+        Ok(())
+    }
+
+    fn on_close(&mut self, _: CloseCode, _: &str) {
+        let _ = self.frames.send(Frame::Closed);
+    }
+}
+
+/// A websocket client connected to the test server, on its own thread.
+struct Client {
+    out: Sender,
+    frames: Receiver<Frame>,
+    token: String,
+}
+
+impl Client {
+    /// Connect to the websocket URL, returning the client along with the state it got on joining.
+    /// The profile every client gets first is checked and kept out of the way.
+    fn join(url: &str) -> (Client, ClientGameState) {
+        let (opened_tx, opened_rx) = mpsc::channel();
+        let (frames_tx, frames_rx) = mpsc::channel();
+        let connect_url = url.to_owned();
+        thread::spawn(move || {
+            ws::connect(connect_url, |out| Forwarder {
+                out,
+                opened: opened_tx.clone(),
+                frames: frames_tx.clone(),
+            })
+            .unwrap()
+        });
+        let out = opened_rx
+            .recv_timeout(TIMEOUT)
+            .unwrap_or_else(|_| panic!("could not connect to {}", url));
+        let mut client = Client {
+            out,
+            frames: frames_rx,
+            token: String::new(),
+        };
+        match client.recv() {
+            ServerMessage::Profile { token, .. } => client.token = token,
+            _ => panic!("expected the profile first"),
+        }
+        let state = client.state();
+        (client, state)
+    }
+
+    fn send(&self, msg: ClientMessage) {
+        self.send_raw(serde_json::to_string(&msg).unwrap());
+    }
+
+    fn send_raw<M: Into<Message>>(&self, msg: M) {
+        self.out.send(msg).unwrap();
+    }
+
+    fn recv(&self) -> ServerMessage {
+        match self.frames.recv_timeout(TIMEOUT) {
+            Ok(Frame::Text(text)) => serde_json::from_str(&text)
+                .unwrap_or_else(|err| panic!("bad message from the server ({}): {}", err, text)),
+            Ok(Frame::Closed) => panic!("connection closed"),
+            Err(_) => panic!("no message from the server"),
+        }
+    }
+
+    /// The next message, which has to be a new state.
+    fn state(&self) -> ClientGameState {
+        match self.recv() {
+            ServerMessage::NewState(state) => state,
+            ServerMessage::Error(error) => panic!("expected a new state, got error: {}", error),
+            _ => panic!("expected a new state"),
+        }
+    }
+
+    /// The next message, which has to be an error.
+    fn error(&self) -> String {
+        match self.recv() {
+            ServerMessage::Error(error) => error,
+            _ => panic!("expected an error"),
+        }
+    }
+
+    /// Check the server doesn't send anything for a while.
+    fn assert_quiet(&self) {
+        match self.frames.recv_timeout(QUIET) {
+            Err(RecvTimeoutError::Timeout) => {}
+            Ok(Frame::Text(text)) => panic!("unexpected message: {}", text),
+            Ok(Frame::Closed) | Err(RecvTimeoutError::Disconnected) => {
+                panic!("unexpected close")
+            }
+        }
+    }
+
+    fn close(self) {
+        self.out.close(CloseCode::Normal).unwrap();
+        // wait for the server to see it, so the client is out of the room before the test goes on
+        loop {
+            match self.frames.recv_timeout(TIMEOUT) {
+                Ok(Frame::Closed) | Err(RecvTimeoutError::Disconnected) => return,
+                Ok(Frame::Text(_)) => {}
+                Err(RecvTimeoutError::Timeout) => panic!("connection didn't close"),
+            }
+        }
+    }
+}
+
+fn phase_name(state: &ClientGameState) -> &'static str {
+    match state.client_phase {
+        ClientPhase::Waiting => "Waiting",
+        ClientPhase::DraftDice(..) => "DraftDice",
+        ClientPhase::SelectAction(..) => "SelectAction",
+        ClientPhase::SelectTarget(..) => "SelectTarget",
+        ClientPhase::ChooseReward(..) => "ChooseReward",
+        ClientPhase::SelectNode(..) => "SelectNode",
+        ClientPhase::RunSummary(_) => "RunSummary",
+        ClientPhase::MatchResult(_) => "MatchResult",
+    }
+}
+
+#[test]
+fn joining_sends_the_profile_and_then_the_room_state() {
+    let server = TestServer::start();
+    let (_client, state) = server.join("/ws/protocol-join");
+
+    assert_eq!(phase_name(&state), "SelectNode");
+    assert!(!state.spectating);
+    assert_eq!(state.seat, None);
+    assert_eq!(state.turn_time_left, None);
+    // the new guest player isn't stored until they change something
+    assert!(!server.dir.join("data").join("players.json").exists());
+}
+
+#[test]
+fn changes_are_broadcast_to_everyone_in_the_room() {
+    let server = TestServer::start();
+    let (player, _) = server.join("/ws/protocol-broadcast");
+    let (watcher, _) = server.join("/ws/protocol-broadcast?spectate");
+
+    player.send(ClientMessage::ChooseNode(0));
+
+    let player_state = player.state();
+    let watcher_state = watcher.state();
+    assert_eq!(phase_name(&player_state), "DraftDice");
+    assert_eq!(phase_name(&watcher_state), "DraftDice");
+    assert!(watcher_state.spectating);
+    assert_eq!(
+        player_state.combat_log.len(),
+        watcher_state.combat_log.len()
+    );
+    player.assert_quiet();
+    watcher.assert_quiet();
+}
+
+#[test]
+fn pvp_players_each_get_their_own_view() {
+    let server = TestServer::start();
+    let (first, first_state) = server.join("/ws/protocol-pvp?pvp");
+    let (second, second_state) = server.join("/ws/protocol-pvp");
+    assert_eq!(first_state.seat, Some(0));
+    assert_eq!(second_state.seat, Some(1));
+
+    // the first seat's team goes first, so the second player has to wait their turn
+    second.send(ClientMessage::FinishDrafting(vec![0]));
+    assert_eq!(second.error(), "It's not your turn");
+    first.assert_quiet();
+
+    first.send(ClientMessage::FinishDrafting(vec![0]));
+    let first_state = first.state();
+    let second_state = second.state();
+    assert_eq!(phase_name(&first_state), "SelectAction");
+    assert_eq!(first_state.seat, Some(0));
+    assert_eq!(second_state.seat, Some(1));
+    // the second player can't see the first team's dice pools
+    let hidden = |state: &ClientGameState| {
+        state.combatants[0]
+            .pool
+            .iter()
+            .all(|die| die.faces.is_empty())
+    };
+    assert!(!hidden(&first_state));
+    assert!(hidden(&second_state));
+}
+
+#[test]
+fn bad_frames_get_an_error_and_the_connection_stays_usable() {
+    let server = TestServer::start();
+    let (client, _) = server.join("/ws/protocol-bad-frames");
+
+    for frame in [
+        "not json",
+        r#"{"NoSuchMessage":1}"#,
+        r#"{"ChooseNode":"first"}"#,
+    ] {
+        client.send_raw(frame);
+        assert!(client.error().starts_with("Invalid message"));
+    }
+
+    // binary frames aren't part of the protocol, and are ignored
+    client.send_raw(vec![1, 2, 3]);
+    client.assert_quiet();
+
+    client.send(ClientMessage::ChooseNode(0));
+    assert_eq!(phase_name(&client.state()), "DraftDice");
+}
+
+#[test]
+fn spectators_cant_act() {
+    let server = TestServer::start();
+    let (player, _) = server.join("/ws/protocol-spectator");
+    let (watcher, _) = server.join("/ws/protocol-spectator?spectate");
+
+    watcher.send(ClientMessage::ChooseNode(0));
+
+    assert_eq!(watcher.error(), "Spectators can't take actions");
+    player.assert_quiet();
+}
+
+#[test]
+fn refused_connections_get_an_http_error() {
+    let server = TestServer::start();
+    assert!(server.http_status("/ws/no%20spaces").contains("400"));
+    assert!(server
+        .http_status("/ws/protocol-refused?no_such_param")
+        .contains("400"));
+    assert!(server
+        .http_status("/ws/protocol-refused?pvp&seat=9")
+        .contains("400"));
+    assert!(server.http_status("/nowhere").contains("404"));
+    assert!(server.http_status("/ws/protocol-refused").contains("101"));
+}
+
+#[test]
+fn reconnecting_picks_the_room_back_up() {
+    let server = TestServer::start();
+    let (client, _) = server.join("/ws/protocol-reconnect");
+    client.send(ClientMessage::ChooseNode(0));
+    let before = client.state();
+    let token = client.token.clone();
+    client.close();
+
+    let (client, after) = server.join(&format!("/ws/protocol-reconnect?token={}", token));
+
+    assert_eq!(phase_name(&after), "DraftDice");
+    assert_eq!(after.combat_log.len(), before.combat_log.len());
+    assert!(matches!(&after.combat_log[0], LogEntry::EnteredNode(_)));
+    // the same player comes back, with the token they were given
+    assert_eq!(client.token, token);
+}
+
+#[test]
+fn reconnecting_pvp_players_get_their_seat_back() {
+    let server = TestServer::start();
+    let (first, _) = server.join("/ws/protocol-rejoin?pvp");
+    let (_second, _) = server.join("/ws/protocol-rejoin");
+    // with both seats taken, anyone else can only watch
+    let (_third, state) = server.join("/ws/protocol-rejoin");
+    assert!(state.spectating);
+    assert_eq!(state.seat, None);
+
+    let token = first.token.clone();
+    first.close();
+    let (_first, state) = server.join(&format!("/ws/protocol-rejoin?token={}", token));
+    assert_eq!(state.seat, Some(0));
+    assert!(!state.spectating);
+}
+
+#[test]
+fn pvp_seats_stay_with_their_players() {
+    let server = TestServer::start();
+    let (first, _) = server.join("/ws/protocol-seats?pvp");
+    let (_second, _) = server.join("/ws/protocol-seats");
+    first.close();
+
+    // the empty seat is still the first player's
+    assert!(server
+        .http_status("/ws/protocol-seats?seat=0")
+        .contains("403"));
+    let (_, state) = server.join("/ws/protocol-seats");
+    assert!(state.spectating);
+}
+
+#[test]
+fn matched_players_get_their_seats_kept_for_them() {
+    let server = TestServer::start();
+    let (first, _) = server.join("/ws/protocol-queue-first");
+    let (second, _) = server.join("/ws/protocol-queue-second");
+    first.send(ClientMessage::FindMatch);
+    assert!(matches!(
+        first.recv(),
+        ServerMessage::Matchmaking(MatchmakingStatus::Searching)
+    ));
+    second.send(ClientMessage::FindMatch);
+    let found = |client: &Client| match client.recv() {
+        ServerMessage::Matchmaking(MatchmakingStatus::Found { room, seat }) => (room, seat),
+        _ => panic!("expected a match"),
+    };
+    let (room, seat) = found(&first);
+    assert_eq!(seat, 0);
+    assert_eq!(found(&second), (room.clone(), 1));
+
+    // nobody else can sit down before them, even if they guess the room code
+    assert!(server
+        .http_status(&format!("/ws/{}?seat=0", room))
+        .contains("403"));
+    let (_, state) = server.join(&format!("/ws/{}", room));
+    assert!(state.spectating);
+
+    for (client, seat) in [(first, 0), (second, 1)] {
+        let path = format!("/ws/{}?seat={}&token={}", room, seat, client.token);
+        let (_, state) = server.join(&path);
+        assert_eq!(state.seat, Some(seat));
+    }
+}
+
+#[test]
+fn finished_matches_free_their_room() {
+    let server = TestServer::start_with(|config| {
+        config.max_rooms = 1;
+        config.pvp_encounter_file =
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests/rosters/duel.json").to_owned();
+    });
+    let (first, _) = server.join("/ws/protocol-duel?pvp");
+    let (second, _) = server.join("/ws/protocol-duel");
+    assert!(server.http_status("/ws/protocol-next").contains("503"));
+
+    // the first seat's Champion knocks out the Dummy with a single attack
+    for msg in [
+        ClientMessage::FinishDrafting(vec![0]),
+        ClientMessage::ChooseAction(0),
+        ClientMessage::ChooseTarget(1),
+    ] {
+        first.send(msg);
+        second.state();
+        let state = first.state();
+        if phase_name(&state) == "MatchResult" {
+            break;
+        }
+    }
+    let (watcher, state) = server.join("/ws/protocol-duel?spectate");
+    assert_eq!(phase_name(&state), "MatchResult");
+    // a finished match keeps its room while anyone is still in it
+    assert!(server.http_status("/ws/protocol-next").contains("503"));
+
+    for client in [first, second, watcher] {
+        client.close();
+    }
+    assert!(server.http_status("/ws/protocol-next").contains("101"));
+}
+
+#[test]
+fn turn_timers_still_run_out_after_whoever_was_checking_them_leaves() {
+    let server = TestServer::start();
+    let (player, _) = server.join("/ws/protocol-timer?turn_timer=1");
+    player.send(ClientMessage::ChooseNode(0));
+    let state = player.state();
+    assert_eq!(state.turn_time_left, Some(1));
+    let (watcher, _) = server.join("/ws/protocol-timer?spectate");
+    player.close();
+
+    // the turn is played for the player once the timer runs out, and only once
+    let state = watcher.state();
+    assert!(state
+        .combat_log
+        .iter()
+        .any(|entry| matches!(entry, LogEntry::TimedOut { .. })));
+    assert_eq!(phase_name(&state), "DraftDice");
+    watcher.assert_quiet();
+}
+
+#[test]
+fn rooms_are_isolated_from_each_other() {
+    let server = TestServer::start();
+    let (here, _) = server.join("/ws/protocol-here");
+    let (there, there_before) = server.join("/ws/protocol-there");
+
+    here.send(ClientMessage::ChooseNode(0));
+    assert_eq!(phase_name(&here.state()), "DraftDice");
+    there.assert_quiet();
+
+    // the other room is still where it was, as a new client there sees
+    let (_, there_after) = server.join("/ws/protocol-there");
+    assert_eq!(phase_name(&there_after), "SelectNode");
+    assert_eq!(there_after.combat_log.len(), there_before.combat_log.len());
+}
@@ -0,0 +1,85 @@
+// Saving rooms and restoring them, which the server does after every change and when it starts.
+mod common;
+
+use common::Battle;
+use dice_combat::config::Config;
+use dice_combat::room::{Role, Room};
+use dice_combat::save::{load_rooms, save_room};
+use dice_combat::shared::{ClientMessage, Face};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+/// An empty directory to save rooms to, only used by the named test.
+fn save_dir(test: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("dice-combat-saves-{}-{}", process::id(), test));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+/// The skirmish roster's run, in the middle of the Hero's first turn.
+fn mid_battle() -> Battle {
+    let mut battle = Battle::campaign("skirmish");
+    battle.play([ClientMessage::ChooseNode(0)]);
+    let draft = battle.draft(&[Face::Number(3)]);
+    battle.play([draft]);
+    battle
+}
+
+#[test]
+fn restored_rooms_carry_on_where_they_were_saved() {
+    let dir = save_dir("round-trip");
+    let path = dir.to_string_lossy();
+    let mut battle = mid_battle();
+    save_room(&path, "round-trip", &battle.room.world).unwrap();
+
+    let mut rooms = load_rooms(&path);
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(rooms.len(), 1);
+    let (code, save) = rooms.pop().unwrap();
+    assert_eq!(code, "round-trip");
+    let mut restored = Room::from_save(&code, save, &Config::default());
+
+    let state =
+        |room: &Room| serde_json::to_string(&room.client_state(Role::Player(None))).unwrap();
+    assert_eq!(state(&restored), state(&battle.room));
+    // the dice and everything else were restored too, so both play the rest of the turn the same way
+    for msg in [
+        ClientMessage::ChooseAction(0),
+        ClientMessage::ChooseTarget(2),
+    ] {
+        battle.send(msg.clone()).unwrap();
+        restored.receive(Role::Player(None), msg).unwrap();
+        assert_eq!(state(&restored), state(&battle.room));
+    }
+}
+
+#[test]
+fn corrupt_saves_are_skipped() {
+    let dir = save_dir("corrupt");
+    let path = dir.to_string_lossy();
+    let battle = mid_battle();
+    save_room(&path, "corrupt", &battle.room.world).unwrap();
+    let file = dir.join("corrupt.json");
+    let save: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&file).unwrap()).unwrap();
+
+    let corruptions: [fn(&mut serde_json::Value); 5] = [
+        |save| save["combat_state"]["combatants"][0] = 99.into(),
+        |save| save["run"]["party"][1] = 99.into(),
+        |save| save["combat_state"]["current_character"] = 99.into(),
+        |save| {
+            save["combat_state"]["current_phase"] =
+                serde_json::json!({ "Action": { "LightAttack": 99 } })
+        },
+        |save| save["combatants"][0]["dice_pool"] = serde_json::Value::Null,
+    ];
+    for corrupt in corruptions {
+        let mut corrupted = save.clone();
+        corrupt(&mut corrupted);
+        fs::write(&file, corrupted.to_string()).unwrap();
+        assert!(load_rooms(&path).is_empty(), "{}", corrupted);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}